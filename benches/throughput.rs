@@ -0,0 +1,112 @@
+// Measures how quickly ds reads large logs, compared to `cat` reading the same file.
+// Run with `cargo bench`, setting DS_BENCH_MB to change the size of each log
+use std::env;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// ====================== Constants =========================
+
+const DEFAULT_SIZE_MB: usize = 20;
+const RUNS: usize = 3;
+
+// ====================== Logs =========================
+
+// Each log repeats its lines until it reaches the size asked for
+struct Log {
+    name: &'static str,
+    lines: &'static [&'static str],
+}
+
+const LOGS: [Log; 3] = [
+    Log {
+        name: "plain text",
+        lines: &["running 120 tests", "test tests::parses_lines ... ok", "test result: ok; 120 passed"],
+    },
+    Log {
+        name: "dotted words",
+        lines: &["   Compiling serde v1.0.197", "see e.g. https://example.com/docs.html", "took 1.5s"],
+    },
+    Log {
+        name: "file locations",
+        lines: &[
+            "error[E0308]: mismatched types",
+            "  --> src/main.rs:12:5",
+            "warning: unused import in src/line_reader.rs:3:1",
+            "  |     Cargo.toml:7",
+        ],
+    },
+];
+
+fn write_log(log: &Log, path: &Path, size: usize) {
+    let mut file = BufWriter::new(fs::File::create(path).unwrap());
+    let mut written = 0;
+    while written < size {
+        for line in log.lines {
+            writeln!(file, "{}", line).unwrap();
+            written += line.len() + 1;
+        }
+    }
+    file.flush().unwrap();
+}
+
+
+// ====================== Timing =========================
+
+// The fastest of a few runs, as slower runs are usually from something else on the machine
+fn time_command(mut make_command: impl FnMut() -> Command) -> Duration {
+    (0..RUNS).map(|_| {
+        let mut command = make_command();
+        command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+
+        let start = Instant::now();
+        let status = command.status().unwrap();
+        assert!(status.success(), "{:?} failed", command);
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn throughput(size: usize, duration: Duration) -> f64 {
+    size as f64 / (1024.0 * 1024.0) / duration.as_secs_f64()
+}
+
+fn main() {
+    let size_mb = env::var("DS_BENCH_MB").ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_SIZE_MB);
+    let size = size_mb * 1024 * 1024;
+
+    // Runs from the crate root, so the files in the logs exist
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let ds = env!("CARGO_BIN_EXE_ds");
+    let log_dir = env::temp_dir().join(format!("ds-bench-{}", std::process::id()));
+    fs::create_dir_all(&log_dir).unwrap();
+
+    println!("{:<16}{:>12}{:>12}{:>10}", "log", "cat MB/s", "ds MB/s", "ds/cat");
+    for log in &LOGS {
+        let path = log_dir.join(log.name.replace(' ', "-"));
+        write_log(log, &path, size);
+
+        let cat = time_command(|| {
+            let mut command = Command::new("cat");
+            command.arg(&path);
+            command
+        });
+        // The run is only stored, so there's no prompt waiting for a file number. It's stored
+        // with the logs, rather than pushing runs out of the real history
+        let ds = time_command(|| {
+            let mut command = Command::new(ds);
+            command.current_dir(&root).args(["--color", "never", "-s", "-g"]).arg(&path)
+                .env("XDG_STATE_HOME", &log_dir)
+                .env("HOME", &log_dir);
+            command
+        });
+
+        println!("{:<16}{:>12.1}{:>12.1}{:>9.1}x", log.name, throughput(size, cat), throughput(size, ds),
+                 ds.as_secs_f64() / cat.as_secs_f64());
+    }
+
+    let _ = fs::remove_dir_all(&log_dir);
+}
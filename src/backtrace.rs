@@ -0,0 +1,143 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+// Rust panics and backtraces print paths as they were when the code was compiled,
+// which don't always exist where the program is run e.g.
+//   at ./src/foo.rs:12:5
+//   at /rustc/<hash>/library/core/src/panicking.rs:72:14
+//   at /usr/local/cargo/registry/src/index.crates.io-6f17d22bba15001f/tokio-1.38.0/src/runtime/mod.rs:9:1
+
+// ====================== Resolving =========================
+
+// Finds the local copy of a Rust source file from a backtrace, if it isn't where it was compiled
+pub fn resolve_rust_path(name: &str) -> Option<PathBuf> {
+    if !name.ends_with(".rs") {
+        return None;
+    }
+
+    if let Some(rest) = name.strip_prefix("/rustc/") {
+        return resolve_standard_library(sysroot()?, rest);
+    }
+    if let Some((_, rest)) = name.split_once("/registry/src/") {
+        return resolve_registry_crate(&cargo_home()?, rest);
+    }
+    if Path::new(name).is_relative() {
+        return resolve_workspace_file(&env::current_dir().ok()?, name);
+    }
+    None
+}
+
+// The standard library is compiled under /rustc/<commit hash>/, and the rust-src
+// component installs the same files into the sysroot
+fn resolve_standard_library(sysroot: &Path, rest: &str) -> Option<PathBuf> {
+    let (_hash, path) = rest.split_once('/')?;
+    let path = sysroot.join("lib/rustlib/src/rust").join(path);
+    path.exists().then_some(path)
+}
+
+// Crates are unpacked under the cargo home of whoever built them e.g. in a container,
+// so the same crate is looked for in the local cargo home
+fn resolve_registry_crate(cargo_home: &Path, rest: &str) -> Option<PathBuf> {
+    let (index, path) = rest.split_once('/')?;
+    let registry = cargo_home.join("registry/src");
+
+    let path_in_index = registry.join(index).join(path);
+    if path_in_index.exists() {
+        return Some(path_in_index);
+    }
+
+    // The index directory's name includes a hash, which can differ between cargo versions
+    fs::read_dir(registry).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join(path))
+        .find(|path| path.exists())
+}
+
+// Cargo compiles workspace crates from the workspace root, so a relative path from a
+// backtrace is looked for in each directory above the current one with a Cargo.toml
+fn resolve_workspace_file(current_dir: &Path, name: &str) -> Option<PathBuf> {
+    current_dir.ancestors()
+        .skip(1)
+        .filter(|dir| dir.join("Cargo.toml").exists())
+        .map(|dir| dir.join(name.trim_start_matches("./")))
+        .find(|path| path.exists())
+}
+
+
+// ====================== Helpers =========================
+
+fn sysroot() -> Option<&'static Path> {
+    static SYSROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    SYSROOT.get_or_init(|| {
+        let output = Command::new("rustc")
+            .args(["--print", "sysroot"])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let sysroot = String::from_utf8(output.stdout).ok()?;
+        (output.status.success() && !sysroot.trim().is_empty()).then(|| PathBuf::from(sysroot.trim()))
+    }).as_deref()
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    match env::var_os("CARGO_HOME") {
+        Some(cargo_home) => Some(PathBuf::from(cargo_home)),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_resolves_rust_files() {
+        assert_eq!(resolve_rust_path("/rustc/abc123/library/core/src/panicking.py"), None);
+        assert_eq!(resolve_rust_path("/tmp/missing/src/lib.rs"), None);
+    }
+
+    #[test]
+    fn resolves_standard_library_from_sysroot() {
+        let sysroot = env::temp_dir().join(format!("ds-test-{}-sysroot", std::process::id()));
+        let expected = sysroot.join("lib/rustlib/src/rust/library/core/src/panicking.rs");
+        fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        fs::write(&expected, "").unwrap();
+
+        assert_eq!(resolve_standard_library(&sysroot, "0123abcd/library/core/src/panicking.rs"), Some(expected));
+        assert_eq!(resolve_standard_library(&sysroot, "0123abcd/library/core/src/missing.rs"), None);
+        let _ = fs::remove_dir_all(&sysroot);
+    }
+
+    #[test]
+    fn resolves_registry_crate_from_cargo_home() {
+        let cargo_home = env::temp_dir().join(format!("ds-test-{}-cargo-home", std::process::id()));
+        let expected = cargo_home.join("registry/src/index.crates.io-1949cf8c6b5b557f/tokio-1.38.0/src/lib.rs");
+        fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        fs::write(&expected, "").unwrap();
+
+        assert_eq!(resolve_registry_crate(&cargo_home, "index.crates.io-1949cf8c6b5b557f/tokio-1.38.0/src/lib.rs"), Some(expected.clone()));
+        // Found under another index directory, as the hash differs between cargo versions
+        assert_eq!(resolve_registry_crate(&cargo_home, "index.crates.io-6f17d22bba15001f/tokio-1.38.0/src/lib.rs"), Some(expected));
+        assert_eq!(resolve_registry_crate(&cargo_home, "index.crates.io-6f17d22bba15001f/tokio-1.38.0/src/missing.rs"), None);
+        let _ = fs::remove_dir_all(&cargo_home);
+    }
+
+    #[test]
+    fn resolves_workspace_file_from_member_crate() {
+        let workspace = env::temp_dir().join(format!("ds-test-{}-workspace", std::process::id()));
+        let member = workspace.join("crates/app");
+        let expected = workspace.join("crates/core/src/lib.rs");
+        fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        fs::create_dir_all(&member).unwrap();
+        fs::write(workspace.join("Cargo.toml"), "[workspace]\n").unwrap();
+        fs::write(&expected, "").unwrap();
+
+        assert_eq!(resolve_workspace_file(&member, "./crates/core/src/lib.rs"), Some(expected));
+        assert_eq!(resolve_workspace_file(&member, "crates/core/src/missing.rs"), None);
+        let _ = fs::remove_dir_all(&workspace);
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::Path;
+
+use crate::input_reader::CommandCall;
+use crate::severity::Rule;
+
+// ====================== Constants =========================
+
+// Flags whose value is the next arg, so it's kept with them rather than taken for a test.
+// For libtest, after the -- in `cargo test`
+const LIBTEST_VALUE_FLAGS: &[&str] = &[
+    "--test-threads", "--skip", "--format", "--color", "--logfile", "--shuffle-seed", "-Z",
+];
+const PYTEST_VALUE_FLAGS: &[&str] = &[
+    "-k", "-m", "-p", "-c", "-o", "-n", "-W", "--rootdir", "--confcutdir", "--basetemp",
+    "--deselect", "--ignore", "--ignore-glob", "--maxfail", "--junitxml", "--junit-xml", "--tb",
+    "--durations", "--cov-report", "--log-level", "--import-mode", "--override-ini", "--capture",
+    "--numprocesses", "--dist",
+];
+
+// ====================== Types =========================
+
+// A test that failed, with the runner it's rerun by
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedTest {
+    pub rule: Rule,
+    pub id: String,
+}
+
+
+// ====================== Finding Failures =========================
+
+// Picks the id of a failed test out of a line of a test runner's output, in the form
+// the runner takes it back to run only that test
+pub fn failed_test(rule: Rule, line: &str) -> Option<String> {
+    let line = line.trim_end();
+    let trimmed = line.trim_start();
+
+    match rule {
+        // test parser::tests::reads_flags ... FAILED
+        Rule::Cargo => trimmed.strip_prefix("test ")
+            .and_then(|rest| rest.strip_suffix(" ... FAILED"))
+            .map(|id| id.to_string()),
+
+        // FAILED tests/test_x.py::test_y - AssertionError
+        // tests/test_x.py::test_y FAILED                  [ 50%]
+        Rule::Pytest => {
+            let id = match trimmed.strip_prefix("FAILED ") {
+                Some(rest) => rest.split(" - ").next().unwrap_or(rest),
+                None => trimmed.split_once(" FAILED").map(|(id, _)| id)?,
+            };
+            id.contains("::").then(|| id.trim().to_string())
+        }
+
+        // --- FAIL: TestThing/sub_test (0.00s)
+        // Subtests are rerun through their top level test
+        Rule::Go => trimmed.strip_prefix("--- FAIL: ")
+            .and_then(|rest| rest.split([' ', '/']).next())
+            .map(|id| id.to_string()),
+
+        // ● Suite › test name
+        // Jest matches tests by their names joined with spaces
+        Rule::Jest => {
+            let name = trimmed.strip_prefix('●')?.trim();
+            (name.contains('›')).then(|| {
+                name.split('›').map(|part| part.trim()).collect::<Vec<_>>().join(" ")
+            })
+        }
+
+        Rule::Make => None,
+    }
+}
+
+
+// ====================== Rerunning =========================
+
+// Builds the command to run only the failed tests, replacing any tests it picked before.
+// `dir` is where the command ran, which its paths are relative to
+pub fn rerun_command(command: &CommandCall, failed: &[FailedTest], dir: &Path) -> Result<CommandCall, String> {
+    let rule = match failed.first() {
+        Some(test) => test.rule,
+        None => return Err(format!("No failed tests were found in the last run of '{}'", command.shell_line())),
+    };
+    let failed: Vec<String> = failed.iter()
+        .filter(|test| test.rule == rule)
+        .map(|test| test.id.clone())
+        .collect();
+
+    let args = match rule {
+        // Filters after -- are matched exactly, so one left in the command matches nothing extra
+        Rule::Cargo => {
+            let mut args: Vec<String> = command.args.iter()
+                .take_while(|arg| *arg != "--")
+                .cloned()
+                .collect();
+            args.push("--".to_string());
+            let test_args: Vec<String> = command.args.iter()
+                .skip_while(|arg| *arg != "--")
+                .skip(1)
+                .cloned()
+                .collect();
+            args.extend(without_positionals(&test_args, LIBTEST_VALUE_FLAGS, |_| true)
+                .into_iter()
+                .filter(|arg| arg != "--exact"));
+            args.push("--exact".to_string());
+            args.extend(failed.iter().cloned());
+            args
+        }
+
+        // Test files and directories would run every test in them
+        Rule::Pytest => {
+            let mut args = without_positionals(&command.args, PYTEST_VALUE_FLAGS, |arg| {
+                arg.contains("::") || dir.join(arg).exists()
+            });
+            args.extend(failed.iter().cloned());
+            args
+        }
+
+        Rule::Go => {
+            let mut args = without_flag(&command.args, &["-run", "--run"]);
+            let names: Vec<String> = dedup(failed.iter().map(|name| regex_escape(name)));
+            args.push("-run".to_string());
+            args.push(format!("^({})$", names.join("|")));
+            args
+        }
+
+        Rule::Jest => {
+            let mut args = without_flag(&command.args, &["-t", "--testNamePattern"]);
+            // `npm test` needs a -- before the arguments for jest
+            if command.command == "npm" && !args.iter().any(|arg| arg == "--") {
+                args.push("--".to_string());
+            }
+            let names: Vec<String> = dedup(failed.iter().map(|name| regex_escape(name)));
+            args.push("-t".to_string());
+            args.push(format!("^({})$", names.join("|")));
+            args
+        }
+
+        Rule::Make => return Err("Failed tests can only be rerun for cargo, pytest, go and jest".to_string()),
+    };
+
+    Ok(CommandCall { command: command.command.clone(), args })
+}
+
+
+// ====================== Helpers =========================
+
+// Removes a flag and its value, given either as a separate arg or after =
+fn without_flag(args: &[String], names: &[&str]) -> Vec<String> {
+    let mut kept = vec!();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if names.contains(&arg.as_str()) {
+            args.next();
+        } else if !names.iter().any(|name| arg.starts_with(&format!("{}=", name))) {
+            kept.push(arg.clone());
+        }
+    }
+    kept
+}
+
+// Removes the args which aren't flags or their values, where `is_removed` picks them
+fn without_positionals(args: &[String], value_flags: &[&str], is_removed: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut kept = vec!();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if value_flags.contains(&arg.as_str()) {
+            kept.push(arg.clone());
+            kept.extend(args.next().cloned());
+        } else if arg.starts_with('-') || !is_removed(arg) {
+            kept.push(arg.clone());
+        }
+    }
+    kept
+}
+
+fn dedup(items: impl Iterator<Item = String>) -> Vec<String> {
+    let mut unique = vec!();
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> CommandCall {
+        let mut words = line.split(' ').map(|word| word.to_string());
+        CommandCall { command: words.next().unwrap(), args: words.collect() }
+    }
+
+    fn failed(rule: Rule, ids: &[&str]) -> Vec<FailedTest> {
+        ids.iter().map(|id| FailedTest { rule, id: id.to_string() }).collect()
+    }
+
+    #[test]
+    fn finds_failed_tests() {
+        assert_eq!(failed_test(Rule::Cargo, "test parser::reads_flags ... FAILED\n"), Some("parser::reads_flags".to_string()));
+        assert_eq!(failed_test(Rule::Cargo, "test parser::reads_flags ... ok"), None);
+        assert_eq!(failed_test(Rule::Pytest, "FAILED tests/test_x.py::test_y[1] - assert 1 == 2"),
+                   Some("tests/test_x.py::test_y[1]".to_string()));
+        assert_eq!(failed_test(Rule::Pytest, "tests/test_x.py::test_y FAILED    [ 50%]"), Some("tests/test_x.py::test_y".to_string()));
+        assert_eq!(failed_test(Rule::Go, "    --- FAIL: TestParse/empty (0.00s)"), Some("TestParse".to_string()));
+        assert_eq!(failed_test(Rule::Jest, "  ● Parser › reads flags"), Some("Parser reads flags".to_string()));
+        assert_eq!(failed_test(Rule::Jest, "  ● Test suite failed to run"), None);
+    }
+
+    #[test]
+    fn reruns_only_failed_tests() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let rerun = rerun_command(&command("cargo test -p ds -- --nocapture old"),
+                                  &failed(Rule::Cargo, &["a::one", "b::two"]), dir).unwrap();
+        assert_eq!(rerun.shell_line(), "cargo test -p ds -- --nocapture --exact a::one b::two");
+
+        let rerun = rerun_command(&command("go test ./... -run=TestOld -v"), &failed(Rule::Go, &["TestA", "TestB"]), dir).unwrap();
+        assert_eq!(rerun.shell_line(), "go test ./... -v -run ^(TestA|TestB)$");
+
+        let rerun = rerun_command(&command("npm test"), &failed(Rule::Jest, &["Parser reads (flags)"]), dir).unwrap();
+        assert_eq!(rerun.shell_line(), "npm test -- -t ^(Parser reads \\(flags\\))$");
+
+        assert!(rerun_command(&command("cargo test"), &[], dir).is_err());
+    }
+
+    #[test]
+    fn reruns_cargo_with_libtest_flag_values() {
+        let failed = failed(Rule::Cargo, &["a::one"]);
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let rerun = rerun_command(&command("cargo test -- --test-threads 1 old"), &failed, dir).unwrap();
+        assert_eq!(rerun.shell_line(), "cargo test -- --test-threads 1 --exact a::one");
+
+        let rerun = rerun_command(&command("cargo test -- --skip slow --exact"), &failed, dir).unwrap();
+        assert_eq!(rerun.shell_line(), "cargo test -- --skip slow --exact a::one");
+    }
+
+    #[test]
+    fn reruns_pytest_without_test_paths() {
+        let failed = failed(Rule::Pytest, &["tests/test_x.py::test_y"]);
+        let rerun = rerun_command(&command("pytest src -x tests/test_x.py::test_z"), &failed,
+                                  Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert_eq!(rerun.shell_line(), "pytest -x tests/test_x.py::test_y");
+
+        // Paths are checked from where the command ran, not where ds is now
+        let rerun = rerun_command(&command("pytest src"), &failed, Path::new("/")).unwrap();
+        assert_eq!(rerun.shell_line(), "pytest src tests/test_x.py::test_y");
+
+        // Flag values which are paths are kept
+        let rerun = rerun_command(&command("pytest --rootdir src tests"), &failed,
+                                  Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert_eq!(rerun.shell_line(), "pytest --rootdir src tests/test_x.py::test_y");
+    }
+}
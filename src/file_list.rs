@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::failures::FailedTest;
+use crate::input_reader::Numbering;
+use crate::line_reader::File;
+
+// The files found in the output, numbered in the order they first appeared. 
+// Depending on the numbering each location in a file gets its own number, or 
+// every mention of a file shares the number of its first location.
+// The tests reported as failing are kept alongside them
+#[derive(Debug)]
+pub struct FileList {
+    numbering: Numbering,
+    files: Vec<File>,
+    positions: HashMap<FileKey, usize>,
+    failed_tests: Vec<FailedTest>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct FileKey {
+    name: String,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+impl FileList {
+    pub fn new(numbering: Numbering) -> FileList {
+        FileList {
+            numbering,
+            files: vec!(),
+            positions: HashMap::new(),
+            failed_tests: vec!(),
+        }
+    }
+
+    fn key(&self, file: &File) -> FileKey {
+        match self.numbering {
+            Numbering::Location => FileKey { name: file.name.clone(), line: file.line, column: file.column },
+            Numbering::File => FileKey { name: file.name.clone(), line: None, column: None },
+        }
+    }
+
+    // Adds a file, returning it with its number if this is the first time it's been seen. 
+    // Repeated files are only counted
+    pub fn add(&mut self, mut file: File) -> Option<&File> {
+        let key = self.key(&file);
+
+        if let Some(&position) = self.positions.get(&key) {
+            self.files[position].hits += 1;
+            return None;
+        }
+
+        file.idx = self.files.len() + 1;
+        self.positions.insert(key, self.files.len());
+        self.files.push(file);
+        self.files.last()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&File> {
+        idx.checked_sub(1).and_then(|position| self.files.get(position))
+    }
+
+    // Files in the order they were numbered
+    pub fn iter(&self) -> impl Iterator<Item = &File> {
+        self.files.iter()
+    }
+
+    // Test runners can report a failure more than once, e.g. again in a summary at the end
+    pub fn add_failed_test(&mut self, test: FailedTest) {
+        if !self.failed_tests.contains(&test) {
+            self.failed_tests.push(test);
+        }
+    }
+
+    pub fn failed_tests(&self) -> &[FailedTest] {
+        &self.failed_tests
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, line: Option<usize>) -> File {
+        File::new(name.to_string(), line, None)
+    }
+
+    #[test]
+    fn numbers_files_in_order() {
+        let mut files = FileList::new(Numbering::Location);
+        files.add(file("b.rs", None));
+        files.add(file("a.rs", None));
+
+        assert_eq!(files.get(1).unwrap().name, "b.rs");
+        assert_eq!(files.get(2).unwrap().name, "a.rs");
+        assert!(files.get(0).is_none());
+        assert!(files.get(3).is_none());
+    }
+
+    #[test]
+    fn numbers_each_location() {
+        let mut files = FileList::new(Numbering::Location);
+        assert_eq!(files.add(file("a.rs", Some(1))).unwrap().idx, 1);
+        assert_eq!(files.add(file("a.rs", Some(2))).unwrap().idx, 2);
+        assert!(files.add(file("a.rs", Some(1))).is_none());
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files.get(1).unwrap().hits, 2);
+    }
+
+    #[test]
+    fn numbers_each_file() {
+        let mut files = FileList::new(Numbering::File);
+        assert_eq!(files.add(file("a.rs", Some(1))).unwrap().idx, 1);
+        assert!(files.add(file("a.rs", Some(2))).is_none());
+        assert_eq!(files.add(file("b.rs", Some(2))).unwrap().idx, 2);
+
+        let first = files.get(1).unwrap();
+        assert_eq!(first.line, Some(1));
+        assert_eq!(first.hits, 2);
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::ignore::find_git_root;
+
+// ====================== Types =========================
+
+// The files in the git repo that are modified, staged or untracked. When a build
+// fails the file that needs opening has usually just been changed
+#[derive(Debug, Default)]
+pub struct GitStatus {
+    changed: HashSet<PathBuf>,
+}
+
+impl GitStatus {
+    // Asks git for the status of the repo the current directory is in. Outside of a
+    // repo, or without git installed, no files are marked as changed
+    pub fn load() -> GitStatus {
+        let root = env::current_dir().ok()
+            .and_then(|dir| find_git_root(&dir))
+            .and_then(|root| fs::canonicalize(root).ok());
+
+        match root {
+            Some(root) => GitStatus::from_repo(&root),
+            None => GitStatus::default(),
+        }
+    }
+
+    fn from_repo(root: &Path) -> GitStatus {
+        let output = Command::new("git")
+            .arg("-C").arg(root)
+            .args(["status", "--porcelain", "-z", "--untracked-files=all"])
+            .stderr(Stdio::null())
+            .output();
+
+        let changed = match output {
+            Ok(output) if output.status.success() => {
+                parse_porcelain(&String::from_utf8_lossy(&output.stdout))
+                    .into_iter()
+                    .map(|path| root.join(path))
+                    .collect()
+            }
+            _ => HashSet::new(),
+        };
+        GitStatus { changed }
+    }
+
+    pub fn is_changed(&self, name: &str) -> bool {
+        if self.changed.is_empty() {
+            return false;
+        }
+        // Deleted files can't be canonicalized, but are still changed
+        let path = fs::canonicalize(name)
+            .or_else(|_| env::current_dir().map(|dir| dir.join(name)));
+        path.is_ok_and(|path| self.changed.contains(&path))
+    }
+}
+
+
+// ====================== Helpers =========================
+
+// Reads the paths from `git status --porcelain -z`, where each entry is "XY path".
+// Renames and copies are followed by the original path, which is no longer there
+fn parse_porcelain(output: &str) -> Vec<&str> {
+    let mut paths = vec!();
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+
+    while let Some(entry) = entries.next() {
+        let Some((status, path)) = entry.split_at_checked(3) else {
+            continue;
+        };
+        if status.contains(['R', 'C']) {
+            entries.next();
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_porcelain_status() {
+        let output = " M src/main.rs\0A  src/git.rs\0?? notes.txt\0R  src/new.rs\0src/old.rs\0";
+        assert_eq!(parse_porcelain(output), vec!("src/main.rs", "src/git.rs", "notes.txt", "src/new.rs"));
+    }
+
+    #[test]
+    fn nothing_changed_outside_a_repo() {
+        let status = GitStatus::default();
+        assert!(!status.is_changed("Cargo.toml"));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::input_reader::Flag;
+
+// ====================== Constants =========================
+
+const SUMMARY: &str = "Runs a command and numbers the files found in its output, so they can be opened";
+const USAGE: &str = "ds [OPTIONS] [--] COMMAND [ARGS...]";
+
+// ====================== Help =========================
+
+pub fn help_text(flags: &[Flag]) -> String {
+    let names: Vec<String> = flags.iter().map(flag_names).collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    let mut text = format!("{SUMMARY}\n\nUsage: {USAGE}\n\nOptions:\n");
+    for (flag, name) in flags.iter().zip(names) {
+        text.push_str(&format!("  {name:width$}   {}\n", flag.description));
+    }
+    text
+}
+
+// Formats the names of a flag for the help text e.g. "-e, --editor <CMD>"
+fn flag_names(flag: &Flag) -> String {
+    let short = match flag.short {
+        Some(c) => format!("-{c}, "),
+        None => "    ".to_string(),
+    };
+    let value = match &flag.value_name {
+        Some(value_name) => format!(" <{value_name}>"),
+        None => String::new(),
+    };
+    format!("{short}--{}{value}", flag.long)
+}
+
+
+// ====================== Man Page =========================
+
+pub fn man_page(flags: &[Flag]) -> String {
+    let mut page = format!(".TH DS 1 \"\" \"ds {}\" \"User Commands\"\n", env!("CARGO_PKG_VERSION"));
+    page.push_str(".SH NAME\nds \\- run a command and open the files found in its output\n");
+    page.push_str(".SH SYNOPSIS\n.B ds\n[\\fIOPTIONS\\fR] [\\fB\\-\\-\\fR] \\fICOMMAND\\fR [\\fIARGS\\fR...]\n");
+    page.push_str(&format!(".SH DESCRIPTION\n{}.\n", roff_escape(SUMMARY)));
+    page.push_str(".PP\nFlags are read up to the first argument which isn't a flag, or up to \\fB\\-\\-\\fR. ");
+    page.push_str("Everything after that is the command to run.\n");
+    page.push_str(".PP\n\\fBds history\\fR lists the runs stored for the current project, most recent first, ");
+    page.push_str("and \\fBds history\\fR \\fIN\\fR picks a file from the Nth of them.\n");
+    page.push_str(".PP\n\\fBds replay\\fR [\\fIN\\fR] shows the output of a run made with \\fB\\-\\-record\\fR again, ");
+    page.push_str("finding the files in it afresh.\n");
+
+    page.push_str(".SH OPTIONS\n");
+    for flag in flags {
+        page.push_str(".TP\n");
+        if let Some(c) = flag.short {
+            page.push_str(&format!("\\fB\\-{}\\fR, ", c));
+        }
+        page.push_str(&format!("\\fB\\-\\-{}\\fR", roff_escape(&flag.long)));
+        if let Some(value_name) = &flag.value_name {
+            page.push_str(&format!(" \\fI{}\\fR", value_name));
+        }
+        page.push_str(&format!("\n{}\n", roff_escape(&flag.description)));
+    }
+
+    page.push_str(".SH ENVIRONMENT\n");
+    page.push_str(".TP\n.B PAGER\nThe pager used for output when stdout is a terminal, \\fBless \\-RFX\\fR by default. \\fBDS_PAGER\\fR is used first if set.\n");
+    page.push_str(".TP\n.B SHELL\nThe shell used by \\fB\\-\\-shell\\fR and \\fB\\-\\-aliases\\fR.\n");
+    page.push_str(".TP\n.B NO_COLOR\nWhen set, file names are underlined rather than coloured.\n");
+    page.push_str(".TP\n.B CLICOLOR_FORCE\nWhen set, file names are highlighted even if stdout isn't a terminal.\n");
+    page.push_str(".TP\n.B DS_COLORS\nStyles for each kind of file, e.g. \"error=1;91:warning=93:note=96:file=92:dir=94:missing=95\".\n");
+    page.push_str(".TP\n.B XDG_STATE_HOME\nRuns are stored in $XDG_STATE_HOME/ds/history, or ~/.local/state/ds/history.\n");
+    page.push_str(".TP\n.B DS_HISTORY_SIZE\nHow many runs are stored, 50 by default. 0 stops runs being stored.\n");
+    page.push_str(".TP\n.B HOME\nUsed to find the nvim server pipe at ~/.config/nvim/server.pipe.\n");
+    page
+}
+
+fn roff_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('-', "\\-")
+}
+
+
+// ====================== Completions =========================
+
+pub fn completions(shell: &str, flags: &[Flag]) -> Result<String, String> {
+    match shell {
+        "bash" => Ok(bash_completions(flags)),
+        "zsh" => Ok(zsh_completions(flags)),
+        "fish" => Ok(fish_completions(flags)),
+        _ => Err(format!("Unknown shell '{}', expected bash, zsh or fish", shell)),
+    }
+}
+
+// All the ways of writing the flags that take their value from the next arg
+fn valued_flag_names(flags: &[Flag]) -> Vec<String> {
+    let mut names = vec!();
+    for flag in flags.iter().filter(|flag| flag.takes_value()) {
+        if let Some(c) = flag.short {
+            names.push(format!("-{c}"));
+        }
+        names.push(format!("--{}", flag.long));
+    }
+    names
+}
+
+fn all_flag_names(flags: &[Flag]) -> Vec<String> {
+    let mut names = vec!();
+    for flag in flags {
+        if let Some(c) = flag.short {
+            names.push(format!("-{c}"));
+        }
+        names.push(format!("--{}", flag.long));
+    }
+    names
+}
+
+// Completes ds's flags up to the wrapped command, then hands over to that command's
+// completion through `_command_offset` from bash-completion
+fn bash_completions(flags: &[Flag]) -> String {
+    let valued = valued_flag_names(flags).join("|");
+    let all = all_flag_names(flags).join(" ");
+
+    let mut choices = String::new();
+    for flag in flags.iter().filter(|flag| !flag.choices.is_empty()) {
+        choices.push_str(&format!("            --{}) COMPREPLY=( $(compgen -W \"{}\" -- \"$cur\") ) ;;\n",
+                                  flag.long, flag.choices.join(" ")));
+    }
+
+    format!(r#"_ds() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local i=1 command_start=0
+
+    # Skip over ds's flags to find where the wrapped command starts
+    while (( i < COMP_CWORD )); do
+        case "${{COMP_WORDS[i]}}" in
+            --) command_start=$(( i + 1 )); break ;;
+            {valued}) (( i += 2 )) ;;
+            -*) (( i++ )) ;;
+            *) command_start=$i; break ;;
+        esac
+    done
+
+    # The cursor is on the value of a flag
+    if (( i > COMP_CWORD )); then
+        case "${{COMP_WORDS[COMP_CWORD-1]}}" in
+{choices}            -e|--editor) COMPREPLY=( $(compgen -c -- "$cur") ) ;;
+            *) COMPREPLY=( $(compgen -f -- "$cur") ) ;;
+        esac
+        return
+    fi
+
+    if (( ! command_start )) && [[ $cur == -* ]]; then
+        COMPREPLY=( $(compgen -W "{all}" -- "$cur") )
+        return
+    fi
+
+    (( command_start )) || command_start=$i
+    if declare -F _command_offset > /dev/null; then
+        _command_offset "$command_start"
+    elif (( command_start == COMP_CWORD )); then
+        COMPREPLY=( $(compgen -c -- "$cur") )
+    else
+        COMPREPLY=( $(compgen -f -- "$cur") )
+    fi
+}}
+complete -F _ds ds
+"#)
+}
+
+// zsh's `_normal` completes the rest of the line as a new command
+fn zsh_completions(flags: &[Flag]) -> String {
+    let mut script = "#compdef ds\n\n_arguments -s -S \\\n".to_string();
+
+    for flag in flags {
+        let description = zsh_escape(&flag.description);
+        let (short, long) = match flag.value_name {
+            Some(_) => (flag.short.map(|c| format!("-{c}+")), format!("--{}=", flag.long)),
+            None => (flag.short.map(|c| format!("-{c}")), format!("--{}", flag.long)),
+        };
+        let names = match short {
+            Some(short) => format!("'(-{} --{})'{{{short},{long}}}", flag.short.unwrap(), flag.long),
+            None => long,
+        };
+        let value = match &flag.value_name {
+            Some(value_name) if !flag.choices.is_empty() => format!(":{value_name}:({})", flag.choices.join(" ")),
+            Some(value_name) if flag.long == "editor" => format!(":{value_name}:_command_names"),
+            Some(value_name) => format!(":{value_name}: "),
+            None => String::new(),
+        };
+        script.push_str(&format!("  {names}'[{description}]{value}' \\\n"));
+    }
+
+    script.push_str("  '(-)*::command:_normal'\n");
+    script
+}
+
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+// Fish's `__fish_complete_subcommand` completes the wrapped command, once one has been started
+fn fish_completions(flags: &[Flag]) -> String {
+    let valued: Vec<String> = valued_flag_names(flags).iter().map(|name| format!("'{name}'")).collect();
+    let valued = valued.join(" ");
+
+    let mut script = format!(r#"function __fish_ds_no_command
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    set -l skip_next 0
+    for token in $tokens
+        if test $skip_next = 1
+            set skip_next 0
+            continue
+        end
+        switch $token
+            case '--'
+                return 1
+            case {valued}
+                set skip_next 1
+            case '-*'
+                continue
+            case '*'
+                return 1
+        end
+    end
+    return 0
+end
+
+"#);
+
+    for flag in flags {
+        let mut line = "complete -c ds -n __fish_ds_no_command".to_string();
+        if let Some(c) = flag.short {
+            line.push_str(&format!(" -s {c}"));
+        }
+        line.push_str(&format!(" -l {}", flag.long));
+        match flag.long.as_str() {
+            "editor" => line.push_str(" -x -a '(__fish_complete_command)'"),
+            _ if !flag.choices.is_empty() => line.push_str(&format!(" -x -a '{}'", flag.choices.join(" "))),
+            _ if flag.takes_value() => line.push_str(" -x"),
+            _ => (),
+        }
+        line.push_str(&format!(" -d '{}'\n", flag.description.replace('\'', "\\'")));
+        script.push_str(&line);
+    }
+
+    script.push_str("complete -c ds -x -a '(__fish_complete_subcommand)'\n");
+    script
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_every_flag() {
+        let flags = Flag::build_flags();
+        let help = help_text(&flags);
+
+        for flag in &flags {
+            assert!(help.contains(&format!("--{}", flag.long)));
+            assert!(help.contains(&flag.description));
+        }
+        assert!(help.contains("-e, --editor <CMD>"));
+    }
+
+    #[test]
+    fn man_page_escapes_dashes() {
+        let man = man_page(&Flag::build_flags());
+        assert!(man.contains("\\fB\\-\\-no\\-colour\\fR"));
+    }
+
+    #[test]
+    fn completions_skip_flag_values() {
+        let flags = Flag::build_flags();
+        let valued: Vec<String> = flags.iter()
+            .filter(|flag| flag.takes_value())
+            .flat_map(|flag| flag.short.map(|c| format!("-{}", c)).into_iter().chain([format!("--{}", flag.long)]))
+            .collect();
+        assert!(valued.contains(&"--editor".to_string()));
+
+        let bash = completions("bash", &flags).unwrap();
+        assert!(bash.contains(&format!("{})", valued.join("|"))));
+        assert!(bash.contains("--sort) COMPREPLY=( $(compgen -W \"appearance path\" -- \"$cur\") ) ;;"));
+
+        let fish = completions("fish", &flags).unwrap();
+        let quoted: Vec<String> = valued.iter().map(|name| format!("'{}'", name)).collect();
+        assert!(fish.contains(&format!("case {}", quoted.join(" "))));
+    }
+
+    #[test]
+    fn rejects_unknown_shell() {
+        assert!(completions("powershell", &Flag::build_flags()).is_err());
+    }
+}
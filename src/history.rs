@@ -0,0 +1,383 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::failures::{rerun_command, FailedTest};
+use crate::file_list::FileList;
+use crate::ignore::find_git_root;
+use crate::input_reader::{CommandCall, Numbering};
+use crate::line_reader::File;
+use crate::severity::{Rule, Severity};
+
+// ====================== Constants =========================
+
+const HISTORY_DIR: &str = "history";
+const DEFAULT_HISTORY_SIZE: usize = 50;
+// Recordings of a run's output are stored next to it, with the same name
+const RECORDING_EXTENSION: &str = "rec";
+
+// ====================== Types =========================
+
+// A command ds ran and what was found in its output. The most recent runs are kept, so 
+// their files can be picked again with --last or `ds history`, and the failed tests rerun 
+// with --rerun-failed
+#[derive(Debug, Default, PartialEq)]
+pub struct StoredRun {
+    // The name of the file the run is stored in
+    pub id: String,
+    pub dir: PathBuf,
+    pub command: CommandCall,
+    pub exit_status: Option<i32>,
+    // Seconds since the Unix epoch
+    pub time: u64,
+    pub files: Vec<File>,
+    pub failed_tests: Vec<FailedTest>,
+}
+
+impl StoredRun {
+    pub fn new(id: String, command: &CommandCall, files: &FileList, exit_status: Option<i32>) -> StoredRun {
+        StoredRun {
+            id,
+            dir: env::current_dir().unwrap_or_default(),
+            command: command.clone(),
+            exit_status,
+            time: now(),
+            files: files.iter().cloned().collect(),
+            failed_tests: files.failed_tests().to_vec(),
+        }
+    }
+
+    // The most recent run, from any directory
+    pub fn latest() -> Result<StoredRun, String> {
+        let path = stored_runs()?.into_iter().next()
+            .ok_or("No runs have been stored yet".to_string())?;
+        load(&path)
+    }
+
+    // The most recent run from inside the current project
+    pub fn latest_in_project() -> Result<StoredRun, String> {
+        project_runs()?.into_iter().next()
+            .ok_or("No runs have been stored for this project yet".to_string())
+    }
+
+    // Adds the run to the history, removing the oldest runs once there are more than
+    // $DS_HISTORY_SIZE of them
+    pub fn save(&self) -> Result<(), String> {
+        let dir = history_dir()?;
+        let path = dir.join(&self.id);
+        let size = history_size();
+        if size == 0 {
+            let _ = fs::remove_file(path.with_extension(RECORDING_EXTENSION));
+            return Ok(());
+        }
+
+        fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(&path, self.to_text()))
+            .map_err(|err| format!("Failed to store the run in '{}', {}", dir.display(), err))?;
+
+        for old_run in stored_runs()?.iter().skip(size) {
+            let _ = fs::remove_file(old_run);
+            let _ = fs::remove_file(old_run.with_extension(RECORDING_EXTENSION));
+        }
+        Ok(())
+    }
+
+    // Removes a run from the history, along with its recording
+    pub fn remove(id: &str) {
+        if let Ok(dir) = history_dir() {
+            let path = dir.join(id);
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(path.with_extension(RECORDING_EXTENSION));
+        }
+    }
+
+    // Where the run's output is recorded with --record
+    pub fn recording_path(id: &str) -> Result<PathBuf, String> {
+        Ok(history_dir()?.join(id).with_extension(RECORDING_EXTENSION))
+    }
+
+    pub fn recording(&self) -> Option<PathBuf> {
+        StoredRun::recording_path(&self.id).ok().filter(|path| path.exists())
+    }
+
+    // Numbers the stored files again. Relative names are made absolute when ds is now
+    // running somewhere else, so they still open the right file
+    pub fn file_list(&self) -> FileList {
+        let moved = env::current_dir().is_ok_and(|dir| dir != self.dir);
+        let mut files = FileList::new(Numbering::Location);
+        for file in &self.files {
+            let mut file = file.clone();
+            if moved && Path::new(&file.name).is_relative() {
+                file.name = self.dir.join(&file.name).to_string_lossy().into_owned();
+            }
+            file.is_dir = Path::new(&file.name).is_dir();
+            files.add(file);
+        }
+        files
+    }
+
+    // The last command, changed to only run the tests that failed. It runs from the
+    // directory the last command did, as the test ids are relative to it
+    pub fn rerun_failed(&self) -> Result<CommandCall, String> {
+        let command = rerun_command(&self.command, &self.failed_tests, &self.dir)?;
+        env::set_current_dir(&self.dir)
+            .map_err(|err| format!("Failed to change to '{}', {}", self.dir.display(), err))?;
+        Ok(command)
+    }
+
+
+    // ====================== Storage Format =========================
+
+    // One record per line, made of tab separated fields with the kind of record first.
+    // Records that aren't recognised are skipped
+    fn to_text(&self) -> String {
+        let mut lines = vec!(
+            record(&["dir", &self.dir.to_string_lossy()]),
+            record(&["command", &self.command.command]),
+        );
+        lines.extend(self.command.args.iter().map(|arg| record(&["arg", arg])));
+        if let Some(code) = self.exit_status {
+            lines.push(record(&["exit", &code.to_string()]));
+        }
+        lines.push(record(&["time", &self.time.to_string()]));
+
+        for file in &self.files {
+            let number = |value: Option<usize>| value.map(|value| value.to_string()).unwrap_or_default();
+            lines.push(record(&[
+                "file", &file.name, &number(file.line), &number(file.column), &file.hits.to_string(),
+                file.severity.name(), file.marker(), &file.message,
+            ]));
+        }
+        for test in &self.failed_tests {
+            lines.push(record(&["failed", test.rule.name(), &test.id]));
+        }
+
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    fn parse(text: &str) -> StoredRun {
+        let mut run = StoredRun::default();
+        for line in text.lines() {
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            let fields: Vec<&str> = fields.iter().map(|field| field.as_str()).collect();
+
+            match fields.as_slice() {
+                ["dir", dir] => run.dir = PathBuf::from(dir),
+                ["command", command] => run.command.command = command.to_string(),
+                ["arg", arg] => run.command.args.push(arg.to_string()),
+                ["exit", code] => run.exit_status = code.parse().ok(),
+                ["time", time] => run.time = time.parse().unwrap_or_default(),
+                ["file", name, line, column, hits, severity, marker, message] => {
+                    let mut file = File::new(name.to_string(), line.parse().ok(), column.parse().ok());
+                    file.hits = hits.parse().unwrap_or(1);
+                    file.severity = Severity::parse(severity).unwrap_or_default();
+                    file.changed = marker.contains('*');
+                    file.missing = marker.contains('?');
+                    file.message = message.to_string();
+                    run.files.push(file);
+                }
+                ["failed", rule, id] => {
+                    if let Ok(rule) = Rule::parse(rule) {
+                        run.failed_tests.push(FailedTest { rule, id: id.to_string() });
+                    }
+                }
+                _ => (),
+            }
+        }
+        run
+    }
+}
+
+
+// ====================== History =========================
+
+// A name for the file a new run is stored in. Sorting the names sorts the runs by
+// when they started
+pub fn new_run_id() -> String {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    format!("{:020}-{}", millis, process::id())
+}
+
+// The runs from inside the current project, i.e. the git repo or the current directory
+// if it isn't in one, with the most recent first. Runs which can't be read, e.g. because
+// another ds removed them in the meantime, are skipped
+pub fn project_runs() -> Result<Vec<StoredRun>, String> {
+    let root = project_root()?;
+    Ok(stored_runs()?.iter()
+        .filter_map(|path| load(path).ok())
+        .filter(|run| run.dir.starts_with(&root))
+        .collect())
+}
+
+pub fn project_root() -> Result<PathBuf, String> {
+    let current_dir = env::current_dir().map_err(|err| format!("Failed to read current directory: {}", err))?;
+    Ok(find_git_root(&current_dir).unwrap_or(current_dir))
+}
+
+// Lists the runs as a table, numbered from the most recent e.g.
+//   #  When     Exit  Files  Directory  Command
+//   1  5m ago      1      3  .          make
+pub fn history_lines(runs: &[StoredRun], root: &Path, now: u64) -> Vec<String> {
+    let dirs: Vec<String> = runs.iter()
+        .map(|run| match run.dir.strip_prefix(root) {
+            Ok(dir) if dir.as_os_str().is_empty() => ".".to_string(),
+            Ok(dir) => dir.to_string_lossy().into_owned(),
+            Err(_) => run.dir.to_string_lossy().into_owned(),
+        })
+        .collect();
+    let dir_width = dirs.iter().map(|dir| dir.len()).max().unwrap_or(0).max("Directory".len());
+    let idx_width = runs.len().to_string().len();
+
+    let mut lines = vec!(format!("{:>idx_width$}  {:<8} {:>5}  {:>5}  {:<dir_width$}  Command", "#", "When", "Exit", "Files", "Directory"));
+    for (idx, (run, dir)) in runs.iter().zip(&dirs).enumerate() {
+        let exit = run.exit_status.map(|code| code.to_string()).unwrap_or("-".to_string());
+        lines.push(format!("{:>idx_width$}  {:<8} {:>5}  {:>5}  {:<dir_width$}  {}", idx + 1, age(now.saturating_sub(run.time)),
+                           exit, run.files.len(), dir, run.command.shell_line()));
+    }
+    lines
+}
+
+fn age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+
+// ====================== Helpers =========================
+
+// Where ds keeps what it needs between runs, following the XDG base directory spec
+pub fn state_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("ds")),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/ds")),
+    }
+}
+
+fn history_dir() -> Result<PathBuf, String> {
+    state_dir()
+        .map(|dir| dir.join(HISTORY_DIR))
+        .ok_or("No state directory, set $XDG_STATE_HOME or $HOME".to_string())
+}
+
+fn history_size() -> usize {
+    env::var("DS_HISTORY_SIZE").ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
+// The files of the stored runs, with the most recent first
+fn stored_runs() -> Result<Vec<PathBuf>, String> {
+    let dir = history_dir()?;
+    let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_none())
+            .collect(),
+        Err(_) => vec!(),
+    };
+    paths.sort();
+    paths.reverse();
+    Ok(paths)
+}
+
+fn load(path: &Path) -> Result<StoredRun, String> {
+    fs::read_to_string(path)
+        .map(|text| StoredRun {
+            id: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            ..StoredRun::parse(&text)
+        })
+        .map_err(|err| format!("Failed to read the run stored in '{}', {}", path.display(), err))
+}
+
+// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn record(fields: &[&str]) -> String {
+    fields.iter().map(|field| escape(field)).collect::<Vec<_>>().join("\t")
+}
+
+// Tabs and newlines are escaped, so they can't split a field or a record
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_reads_back_the_run() {
+        let mut files = FileList::new(Numbering::Location);
+        let mut file = File::new("src/main.rs".to_string(), Some(12), Some(5));
+        file.severity = Severity::Error;
+        file.changed = true;
+        file.message = "error:\tmismatched types\n".to_string();
+        files.add(file);
+        files.add(File::new("Cargo.toml".to_string(), None, None));
+        files.add_failed_test(FailedTest { rule: Rule::Cargo, id: "tests::parses".to_string() });
+
+        let command = CommandCall { command: "cargo".to_string(), args: vec!("test".to_string(), "a\\b".to_string()) };
+        let run = StoredRun::new(String::new(), &command, &files, Some(101));
+        let read_back = StoredRun::parse(&run.to_text());
+
+        assert_eq!(read_back, run);
+        assert_eq!(read_back.files[0].message, "error:\tmismatched types\n");
+        assert!(read_back.files[0].changed);
+        assert_eq!(read_back.command.args[1], "a\\b");
+    }
+
+    #[test]
+    fn lists_runs_most_recent_first() {
+        let run = |dir: &str, command: &str, time: u64| StoredRun {
+            dir: PathBuf::from(dir),
+            command: CommandCall { command: command.to_string(), args: vec!() },
+            exit_status: Some(2),
+            time,
+            ..StoredRun::default()
+        };
+        let runs = vec!(run("/project", "make", 10000), run("/project/tests", "pytest", 10000 - 7200));
+        let lines = history_lines(&runs, Path::new("/project"), 10030);
+
+        assert_eq!(lines, vec!(
+            "#  When      Exit  Files  Directory  Command",
+            "1  30s ago      2      0  .          make",
+            "2  2h ago       2      0  tests      pytest",
+        ));
+    }
+
+    #[test]
+    fn skips_unknown_records() {
+        let run = StoredRun::parse("command\tmake\nnewer\tthing\nfailed\tant\tx\n");
+        assert_eq!(run.command.command, "make");
+        assert!(run.failed_tests.is_empty());
+    }
+}
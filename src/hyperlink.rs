@@ -0,0 +1,97 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::line_reader::File;
+
+// ====================== Constants =========================
+
+const OSC_8_START: &str = "\x1B]8;;";
+const STRING_TERMINATOR: &str = "\x1B\\";
+
+// ====================== Hyperlinks =========================
+
+// Wraps text in an OSC 8 hyperlink, which supporting terminals make clickable
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("{OSC_8_START}{url}{STRING_TERMINATOR}{text}{OSC_8_START}{STRING_TERMINATOR}")
+}
+
+// Builds the link target for a file. Without a format this is a file:// URL, otherwise 
+// {path}, {line} and {col} in the format are filled in e.g. "vscode://file{path}:{line}:{col}"
+pub fn file_url(file: &File, format: Option<&str>) -> String {
+    let path = percent_encode(&absolute_path(&file.name).to_string_lossy());
+
+    match format {
+        Some(format) => format
+            .replace("{path}", &path)
+            .replace("{line}", &file.line.unwrap_or(1).to_string())
+            .replace("{col}", &file.column.unwrap_or(1).to_string()),
+        None => format!("file://{}{}", hostname(), path),
+    }
+}
+
+pub fn absolute_path(name: &str) -> PathBuf {
+    fs::canonicalize(name).unwrap_or_else(|_| {
+        env::current_dir()
+            .map(|dir| dir.join(name))
+            .unwrap_or_else(|_| Path::new(name).to_path_buf())
+    })
+}
+
+// Terminals use the host to check the file is on this machine, rather than over ssh
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        fs::read_to_string("/proc/sys/kernel/hostname")
+            .or_else(|_| fs::read_to_string("/etc/hostname"))
+            .map(|name| name.trim().to_string())
+            .unwrap_or_default()
+    })
+}
+
+// Escapes everything in a path except unreserved characters and the separators
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_text_in_osc_8() {
+        let link = hyperlink("file:///a.rs", "[1. a.rs]");
+        assert_eq!(link, "\x1B]8;;file:///a.rs\x1B\\[1. a.rs]\x1B]8;;\x1B\\");
+    }
+
+    #[test]
+    fn escapes_paths() {
+        assert_eq!(percent_encode("/src/my file#1.rs"), "/src/my%20file%231.rs");
+    }
+
+    #[test]
+    fn fills_in_url_format() {
+        let file = File::new("/tmp/a.rs".to_string(), Some(12), None);
+        let url = file_url(&file, Some("vscode://file{path}:{line}:{col}"));
+        assert!(url.starts_with("vscode://file/"));
+        assert!(url.ends_with("a.rs:12:1"));
+    }
+
+    #[test]
+    fn builds_file_url() {
+        let file = File::new("Cargo.toml".to_string(), None, None);
+        let url = file_url(&file, None);
+        assert!(url.starts_with("file://"));
+        assert!(url.ends_with("/Cargo.toml"));
+    }
+}
@@ -0,0 +1,333 @@
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::history::state_dir;
+use crate::input_reader::Config;
+
+// ====================== Constants =========================
+
+// Never rerun for in watch mode, even without a .gitignore, as the command writes to them
+const WATCH_IGNORES: &[&str] = &["target/", ".git/"];
+
+// ====================== Types =========================
+
+// What part of a path a pattern is matched against
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Anchor {
+    // Starts with / or ~ in an --ignore flag, matched against the absolute path
+    Absolute,
+    // Contains a /, matched against the path from the project root
+    Root,
+    // Matched against the name of the file, or of any directory it's in
+    Anywhere,
+    // Like Anywhere, but only for the directories inside the git repo, as a .gitignore
+    // says nothing about the directories above it
+    InRepo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    glob: String,
+    anchor: Anchor,
+    negated: bool,
+    dir_only: bool,
+}
+
+// Decides which files are ignored, so they're shown but not numbered. Patterns follow
+// the rules of .gitignore, where the last pattern to match a file wins
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    current_dir: PathBuf,
+    patterns: Vec<Pattern>,
+    project_only: bool,
+}
+
+impl Pattern {
+    // Parses a line of a .gitignore file, or an --ignore flag if allow_absolute is set
+    fn parse(line: &str, allow_absolute: bool) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        let (anchor, glob) = if allow_absolute && (line.starts_with('/') || line.starts_with("~/")) {
+            (Anchor::Absolute, expand_home(line))
+        } else if line.contains('/') {
+            (Anchor::Root, line.trim_start_matches('/').to_string())
+        } else if allow_absolute {
+            (Anchor::Anywhere, line.to_string())
+        } else {
+            (Anchor::InRepo, line.to_string())
+        };
+
+        Some(Pattern { glob, anchor, negated, dir_only })
+    }
+
+    fn matches(&self, absolute: &Path, relative: Option<&Path>, is_dir: bool) -> bool {
+        // A file is also ignored when any of the directories it's in are
+        let skip = if self.dir_only && !is_dir { 1 } else { 0 };
+
+        match self.anchor {
+            Anchor::Absolute => absolute.ancestors().skip(skip)
+                .any(|path| glob_match(&self.glob, &path.to_string_lossy())),
+            Anchor::Root => relative.is_some_and(|relative| {
+                relative.ancestors().skip(skip)
+                    .any(|path| glob_match(&self.glob, &path.to_string_lossy()))
+            }),
+            Anchor::Anywhere => absolute.ancestors().skip(skip)
+                .filter_map(|path| path.file_name())
+                .any(|name| glob_match(&self.glob, &name.to_string_lossy())),
+            Anchor::InRepo => relative.is_some_and(|relative| {
+                relative.ancestors().skip(skip)
+                    .filter_map(|path| path.file_name())
+                    .any(|name| glob_match(&self.glob, &name.to_string_lossy()))
+            }),
+        }
+    }
+}
+
+impl IgnoreRules {
+    pub fn from_config(config: &Config) -> IgnoreRules {
+        IgnoreRules::new(config, config.gitignore, vec!())
+    }
+
+    // The files which don't trigger a rerun in watch mode. The .gitignore is always used
+    // here, as otherwise a build writing to its output directory would start another build.
+    // So are the usual output directories and where ds saves its runs, for projects without one
+    pub fn for_watching(config: &Config) -> IgnoreRules {
+        IgnoreRules::new(config, true, watch_patterns(state_dir().as_deref()))
+    }
+
+    fn new(config: &Config, gitignore: bool, mut patterns: Vec<Pattern>) -> IgnoreRules {
+        let current_dir = env::current_dir().unwrap_or_default();
+        let git_root = find_git_root(&current_dir);
+
+        if gitignore {
+            if let Some(git_root) = &git_root {
+                for file in [git_root.join(".gitignore"), git_root.join(".git/info/exclude")] {
+                    let lines = fs::read_to_string(file).unwrap_or_default();
+                    patterns.extend(lines.lines().filter_map(|line| Pattern::parse(line, false)));
+                }
+            }
+        }
+        // Flags come last so that they take priority
+        patterns.extend(config.ignore.iter().filter_map(|glob| Pattern::parse(glob, true)));
+
+        IgnoreRules {
+            root: git_root.unwrap_or_else(|| current_dir.clone()),
+            current_dir,
+            patterns,
+            project_only: config.project_only,
+        }
+    }
+
+    pub fn is_ignored(&self, name: &str) -> bool {
+        self.is_ignored_path(name, false)
+    }
+
+    // Patterns ending in / match a directory itself, as well as the files in it
+    pub fn is_ignored_dir(&self, name: &str) -> bool {
+        self.is_ignored_path(name, true)
+    }
+
+    fn is_ignored_path(&self, name: &str, is_dir: bool) -> bool {
+        let absolute = normalise(&self.current_dir.join(name));
+        let relative = absolute.strip_prefix(&self.root).ok();
+
+        if self.project_only && relative.is_none() {
+            return true;
+        }
+
+        let mut ignored = false;
+        for pattern in &self.patterns {
+            if pattern.matches(&absolute, relative, is_dir) {
+                ignored = !pattern.negated;
+            }
+        }
+        ignored
+    }
+}
+
+
+// ====================== Helpers =========================
+
+// The state directory is only matched when it's absolute, as it is unless $XDG_STATE_HOME
+// is set to a relative path
+fn watch_patterns(state_dir: Option<&Path>) -> Vec<Pattern> {
+    let state_dir = state_dir
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| format!("{}/", dir.to_string_lossy().trim_end_matches('/')));
+    WATCH_IGNORES.iter().map(|glob| glob.to_string())
+        .chain(state_dir)
+        .filter_map(|glob| Pattern::parse(&glob, true))
+        .collect()
+}
+
+// Finds the root of the git repo that a directory is in
+pub fn find_git_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(|dir| dir.to_path_buf())
+}
+
+fn expand_home(glob: &str) -> String {
+    match (glob.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => glob.to_string(),
+    }
+}
+
+// Removes . and .. from a path without touching the file system
+fn normalise(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => { normalised.pop(); },
+            other => normalised.push(other),
+        }
+    }
+    normalised
+}
+
+// Matches a glob where * and ? don't match a /, but ** matches any number of directories
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.starts_with(b"**") => {
+            let rest = &pattern[2..];
+            // "**/" can also match no directories at all
+            if let Some(after_slash) = rest.strip_prefix(b"/") {
+                if glob_match_bytes(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|idx| glob_match_bytes(rest, &text[idx..]))
+        }
+        Some(b'*') => {
+            for idx in 0..=text.len() {
+                if glob_match_bytes(&pattern[1..], &text[idx..]) {
+                    return true;
+                }
+                if idx < text.len() && text[idx] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !text.is_empty() && text[0] != b'/' && glob_match_bytes(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && glob_match_bytes(&pattern[1..], &text[1..]),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(patterns: &[&str], gitignore: &[&str]) -> IgnoreRules {
+        let mut all: Vec<Pattern> = gitignore.iter().filter_map(|line| Pattern::parse(line, false)).collect();
+        all.extend(patterns.iter().filter_map(|glob| Pattern::parse(glob, true)));
+        IgnoreRules {
+            root: PathBuf::from("/project"),
+            current_dir: PathBuf::from("/project/sub"),
+            patterns: all,
+            project_only: false,
+        }
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(glob_match("**", "a/b"));
+        assert!(glob_match("ma?n.rs", "main.rs"));
+        assert!(!glob_match("ma?n.rs", "ma/n.rs"));
+    }
+
+    #[test]
+    fn ignores_directories_anywhere_in_repo() {
+        let rules = rules(&[], &["target/", "node_modules"]);
+        assert!(rules.is_ignored("target/debug/build.rs"));
+        assert!(rules.is_ignored("/project/web/node_modules/a.js"));
+        assert!(!rules.is_ignored("/elsewhere/node_modules/a.js"));
+        assert!(!rules.is_ignored("src/target.rs"));
+        assert!(rules.is_ignored_dir("target"));
+        assert!(!rules.is_ignored("target"));
+    }
+
+    #[test]
+    fn ignores_nothing_above_repo() {
+        let mut rules = rules(&[], &["build"]);
+        rules.root = PathBuf::from("/tmp/build/project");
+        rules.current_dir = rules.root.clone();
+        assert!(!rules.is_ignored("src/main.rs"));
+        assert!(!rules.is_ignored_dir("src"));
+        assert!(rules.is_ignored("build/out.c"));
+    }
+
+    #[test]
+    fn matches_flags_anywhere() {
+        let rules = rules(&["node_modules"], &[]);
+        assert!(rules.is_ignored("/elsewhere/node_modules/a.js"));
+    }
+
+    #[test]
+    fn anchors_patterns_with_slashes() {
+        let rules = rules(&[], &["/build", "docs/*.md"]);
+        assert!(rules.is_ignored("/project/build/out.c"));
+        assert!(!rules.is_ignored("build/out.c"));
+        assert!(rules.is_ignored("../docs/intro.md"));
+    }
+
+    #[test]
+    fn later_patterns_win() {
+        let rules = rules(&["!keep.log"], &["*.log"]);
+        assert!(rules.is_ignored("debug.log"));
+        assert!(!rules.is_ignored("keep.log"));
+    }
+
+    #[test]
+    fn matches_absolute_flags() {
+        let rules = rules(&["/usr/lib/**"], &[]);
+        assert!(rules.is_ignored("/usr/lib/python3/os.py"));
+        assert!(!rules.is_ignored("lib/os.py"));
+    }
+
+    #[test]
+    fn ignores_output_and_state_when_watching() {
+        let mut rules = rules(&[], &[]);
+        rules.patterns = watch_patterns(Some(Path::new("/project/.state/ds")));
+        assert!(rules.is_ignored("target/debug/ds"));
+        assert!(rules.is_ignored_dir("/project/.git"));
+        assert!(rules.is_ignored("/project/.state/ds/history/1.json"));
+        assert!(!rules.is_ignored("/project/.state/other.json"));
+        assert!(!rules.is_ignored("src/main.rs"));
+        assert_eq!(watch_patterns(Some(Path::new("state/ds"))).len(), WATCH_IGNORES.len());
+    }
+
+    #[test]
+    fn ignores_files_outside_project() {
+        let mut rules = rules(&[], &[]);
+        rules.project_only = true;
+        assert!(!rules.is_ignored("src/main.rs"));
+        assert!(rules.is_ignored("/usr/lib/python3/os.py"));
+    }
+}
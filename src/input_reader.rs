@@ -1,8 +1,69 @@
 
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::io::{Seek, SeekFrom};
+use std::time::Duration;
+
+use crate::theme::{ColourMode, Theme};
+use crate::severity::Rule;
+use crate::remap::Remap;
+use crate::pager::Paging;
+
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum SortOrder {
+    #[default]
+    Appearance,
+    Path,
+}
+
+impl SortOrder {
+    fn parse(value: &str) -> Result<SortOrder, String> {
+        match value {
+            "appearance" => Ok(SortOrder::Appearance),
+            "path" => Ok(SortOrder::Path),
+            _ => Err(format!("Invalid sort order '{}', expected appearance or path", value)),
+        }
+    }
+}
+
+// How the files found are reported
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    JsonLines,
+}
+
+// Whether each location in a file gets its own number, or just each file
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum Numbering {
+    #[default]
+    Location,
+    File,
+}
 
-#[derive(Debug, PartialEq)]
+impl Numbering {
+    fn parse(value: &str) -> Result<Numbering, String> {
+        match value {
+            "location" => Ok(Numbering::Location),
+            "file" => Ok(Numbering::File),
+            _ => Err(format!("Invalid numbering '{}', expected location or file", value)),
+        }
+    }
+}
+
+// Commands of ds itself, given in place of a command to run
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Subcommand {
+    // `ds history` lists the stored runs, and `ds history 3` picks a file from the third
+    History(Option<usize>),
+    // `ds replay` shows the most recent recorded output again, and `ds replay 3` the third run's
+    Replay(Option<usize>),
+}
+
+#[derive(Debug, PartialEq, Default)]
 pub struct Config {
     pub no_colour: bool , 
     pub last_files: bool, 
@@ -11,39 +72,85 @@ pub struct Config {
     pub files_only: bool,
     pub open_here: bool,
     pub print_help: bool,
+    pub log_file: bool,
+    pub tail: bool,
+    pub shell: bool,
+    pub aliases: bool,
+    pub editor: Option<String>,
+    pub rules: Vec<Rule>,
+    pub completions: Option<String>,
+    pub print_man: bool,
+    pub sort: SortOrder,
+    pub summary: bool,
+    pub summary_limit: Option<usize>,
+    pub numbering: Numbering,
+    pub colour: ColourMode,
+    pub theme: Theme,
+    pub hyperlinks: bool,
+    pub link_format: Option<String>,
+    pub format: OutputFormat,
+    pub errors_only: bool,
+    pub ignore: Vec<String>,
+    pub gitignore: bool,
+    pub project_only: bool,
+    pub remaps: Vec<Remap>,
+    pub allow_missing: bool,
+    pub timeout: Option<Duration>,
+    pub watch: bool,
+    pub rerun_failed: bool,
+    pub subcommand: Option<Subcommand>,
+    pub record: bool,
+    pub paging: Paging,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            no_colour: false,
-            last_files: false,
-            replay_last: false,
-            store_only: false,
-            files_only: false,
-            open_here: false,
-            print_help: false,
-        }
-    }
-}
 
 #[derive(Debug)]
-struct Flag {
-    short: char, 
-    long: String,
-    description: String,
+pub struct Flag {
+    pub short: Option<char>, 
+    pub long: String,
+    pub value_name: Option<String>,
+    pub choices: Vec<String>,
+    pub description: String,
 }
 
 impl Flag {
     fn new(short: char, long: &str, description: &str) -> Flag {
         Flag {
-            short: short.into(),
+            short: Some(short),
             long: long.into(),
+            value_name: None,
+            choices: vec!(),
             description: description.into(),
         }
     }
 
-    fn build_flags() -> Vec<Flag> {
+    fn long_only(long: &str, description: &str) -> Flag {
+        Flag {
+            short: None,
+            long: long.into(),
+            value_name: None,
+            choices: vec!(),
+            description: description.into(),
+        }
+    }
+
+    // Marks the flag as taking a value, e.g. `--editor code` or `--editor=code`
+    fn with_value(mut self, value_name: &str) -> Flag {
+        self.value_name = Some(value_name.into());
+        self
+    }
+
+    // The fixed set of values a flag accepts, used for completions
+    fn with_choices(mut self, choices: &[&str]) -> Flag {
+        self.choices = choices.iter().map(|choice| choice.to_string()).collect();
+        self
+    }
+
+    pub fn takes_value(&self) -> bool {
+        self.value_name.is_some()
+    }
+
+    pub fn build_flags() -> Vec<Flag> {
         vec!(
             Flag::new('c', "no-colour",   "Prints output without coloring file names"),
             Flag::new('l', "last",        "Prints the stored filenames from the last run"),
@@ -52,36 +159,185 @@ impl Flag {
             Flag::new('f', "files-only",  "Prints only the filenames, not surrounding context"),
             Flag::new('o', "open-here",   "Open file in current terminal, not using nvim server"),
             Flag::new('h', "help",        "Prints this message and exits"),
+            Flag::new('g', "log",         "Reads the output from a log file instead of running a command"),
+            Flag::new('t', "tail",        "Reads a log file and keeps following it as it grows"),
+            Flag::new('x', "shell",       "Runs the command through $SHELL, allowing pipes and globs"),
+            Flag::new('a', "aliases",     "Runs the command through an interactive $SHELL, allowing aliases"),
+            Flag::new('e', "editor",      "Opens the selected file with this command instead of nvim").with_value("CMD"),
+            Flag::long_only("rule",       "Uses the output rules for a tool, can be repeated").with_value("TOOL")
+                .with_choices(&["cargo", "pytest", "go", "jest", "make"]),
+            Flag::long_only("sort",       "Sorts the files only list by appearance or path").with_value("ORDER")
+                .with_choices(&["appearance", "path"]),
+            Flag::new('u', "summary",     "Prints a table of the files found after the command finishes"),
+            Flag::long_only("summary-limit", "Only shows the last N files in the summary").with_value("N"),
+            Flag::long_only("number-by",  "Numbers each location, or only each file").with_value("WHAT")
+                .with_choices(&["location", "file"]),
+            Flag::long_only("color",      "Whether to colour file names: auto, always or never").with_value("WHEN")
+                .with_choices(&["auto", "always", "never"]),
+            Flag::long_only("paging",     "Whether to show output through $PAGER: auto, always or never").with_value("WHEN")
+                .with_choices(&["auto", "always", "never"]),
+            Flag::long_only("hyperlinks", "Makes file names clickable links in terminals that support it"),
+            Flag::long_only("link-format", "Links to a URL like vscode://file{path}:{line}:{col}").with_value("URL"),
+            Flag::long_only("json",       "Prints the files found as a JSON document, instead of the output"),
+            Flag::long_only("jsonl",      "Prints each file found as a line of JSON, instead of the output"),
+            Flag::new('E', "errors-only", "Only numbers files found on lines with errors"),
+            Flag::long_only("ignore",     "Shows files matching a glob without numbering them, can be repeated").with_value("GLOB"),
+            Flag::long_only("gitignore",  "Also ignores files matched by the repo's .gitignore and .git/info/exclude"),
+            Flag::long_only("remap",      "Replaces the start of paths e.g. /app=. for a container, can be repeated")
+                .with_value("FROM=TO"),
+            Flag::new('M', "allow-missing", "Also numbers paths which look like files, but don't exist"),
+            Flag::new('P', "project-only", "Only numbers files inside the current git repo or directory"),
+            Flag::long_only("record",     "Records the output, so it can be shown again with `ds replay`"),
+            Flag::long_only("rerun-failed", "Runs only the tests which failed in the last run"),
+            Flag::new('w', "watch",       "Reruns the command whenever a file in the current directory changes"),
+            Flag::long_only("timeout",    "Stops the command after a time like 90s, 5m or 1h, then picks a file").with_value("DUR"),
+            Flag::long_only("completions", "Prints a completion script for bash, zsh or fish").with_value("SHELL")
+                .with_choices(&["bash", "zsh", "fish"]),
+            Flag::long_only("man",        "Prints a man page in roff format"),
         )
     }
+
+    fn find_long<'a>(flags: &'a [Flag], name: &str) -> Option<&'a Flag> {
+        flags.iter().find(|flag| flag.long == name)
+    }
+
+    fn find_short(flags: &[Flag], c: char) -> Option<&Flag> {
+        flags.iter().find(|flag| flag.short == Some(c))
+    }
 }
 
 impl Config {
-    fn set_flag(&mut self, flag: &Flag) {
-        match flag.short {
-            'c' => self.no_colour = true,
-            'l' => self.last_files = true,
-            'r' => self.replay_last = true,
-            's' => self.store_only = true,
-            'f' => self.files_only = true,
-            'h' => self.print_help = true,
+    // Checks if ds should print some information and exit, rather than running a command
+    pub fn prints_info(&self) -> bool {
+        self.print_help || self.print_man || self.completions.is_some()
+    }
+
+    // Checks if the command's output is printed as it's read, which it isn't when 
+    // only files are being listed
+    pub fn shows_output(&self) -> bool {
+        !self.files_only && self.format == OutputFormat::Text
+    }
+
+    fn set_flag(&mut self, flag: &Flag, value: Option<String>) -> Result<(), String> {
+        match (flag.long.as_str(), value) {
+            ("no-colour", _) => self.no_colour = true,
+            ("last", _) => self.last_files = true,
+            ("replay-last", _) => self.replay_last = true,
+            ("store", _) => self.store_only = true,
+            ("files-only", _) => self.files_only = true,
+            ("open-here", _) => self.open_here = true,
+            ("help", _) => self.print_help = true,
+            ("log", _) => self.log_file = true,
+            ("tail", _) => {
+                self.log_file = true;
+                self.tail = true;
+            }
+            ("shell", _) => self.shell = true,
+            ("aliases", _) => {
+                self.shell = true;
+                self.aliases = true;
+            }
+            ("editor", Some(value)) => self.editor = Some(value),
+            ("rule", Some(value)) => self.rules.push(Rule::parse(&value)?),
+            ("completions", Some(value)) => self.completions = Some(value),
+            ("man", _) => self.print_man = true,
+            ("sort", Some(value)) => self.sort = SortOrder::parse(&value)?,
+            ("summary", _) => self.summary = true,
+            ("summary-limit", Some(value)) => {
+                let limit = value.parse()
+                    .map_err(|_| format!("{}, is not a valid number of files", value))?;
+                self.summary = true;
+                self.summary_limit = Some(limit);
+            }
+            ("number-by", Some(value)) => self.numbering = Numbering::parse(&value)?,
+            ("color", Some(value)) => self.colour = ColourMode::parse(&value)?,
+            ("paging", Some(value)) => self.paging = Paging::parse(&value)?,
+            ("hyperlinks", _) => self.hyperlinks = true,
+            ("link-format", Some(value)) => {
+                self.hyperlinks = true;
+                self.link_format = Some(value);
+            }
+            ("json", _) => self.format = OutputFormat::Json,
+            ("jsonl", _) => self.format = OutputFormat::JsonLines,
+            ("errors-only", _) => self.errors_only = true,
+            ("ignore", Some(value)) => self.ignore.push(value),
+            ("gitignore", _) => self.gitignore = true,
+            ("project-only", _) => self.project_only = true,
+            ("allow-missing", _) => self.allow_missing = true,
+            ("remap", Some(value)) => self.remaps.push(Remap::parse(&value)?),
+            ("watch", _) => self.watch = true,
+            ("rerun-failed", _) => self.rerun_failed = true,
+            ("record", _) => self.record = true,
+            ("timeout", Some(value)) => self.timeout = Some(parse_duration(&value)?),
             _ => unreachable!("Invalid input flag passed to set_flag")
         }
+        Ok(())
     }
 }
 
 
-#[derive(Debug)]
+// Subcommands take the number of a run, counting back from the most recent
+fn parse_run_number(subcommand: &str, args: &[String]) -> Result<Option<usize>, String> {
+    match args {
+        [] => Ok(None),
+        [run] => match run.parse::<usize>() {
+            Ok(run) if run > 0 => Ok(Some(run)),
+            _ => Err(format!("{}, is not a run number", run)),
+        },
+        _ => Err(format!("`ds {}` takes at most one run number", subcommand)),
+    }
+}
+
+// Reads a duration like 500ms, 90s, 5m or 1h, where a plain number is in seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse()
+        .map_err(|_| format!("{}, is not a valid duration", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 60.0 * 60.0,
+        _ => return Err(format!("Invalid duration unit '{}', expected ms, s, m or h", unit)),
+    };
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("{}, is too long a duration", value))
+}
+
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct CommandCall {
     pub command: String,
     pub args: Vec<String>,
 }
 
+impl CommandCall {
+    // Joins the command back into a single line to be run by a shell
+    pub fn shell_line(&self) -> String {
+        let mut line = self.command.clone();
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        line
+    }
+}
+
 
 pub fn read_args(args: Vec<String>) -> Result<(CommandCall, Config), String> {
-    let (config_args, command_args) = split_config_command(args);
+    let (mut config_args, mut command_args, mut after_separator) = split_config_command(args);
+
+    // Strip off any nested calls to ds, merging their flags in with the outer call
+    while command_args.first().is_some_and(|command| is_ds_command(command)) {
+        let (nested_config_args, nested_command_args, nested_after_separator) = split_config_command(command_args);
+        config_args.extend(nested_config_args);
+        command_args = nested_command_args;
+        after_separator = nested_after_separator;
+    }
     
-    let config = parse_config(config_args)?;
+    let mut config = parse_config(config_args)?;
+    config.theme = Theme::detect(config.colour, config.no_colour);
 
     /*
     let command = if config.replay_last {
@@ -90,70 +346,178 @@ pub fn read_args(args: Vec<String>) -> Result<(CommandCall, Config), String> {
     else {
     };
     */
-    let  command = parse_command(command_args)?;
-
-    // TODO: strip of the ds parts and call the underlying command
-    if command.command == "ds" {
-        return Err(format!("Command is a recursive call to 'ds'"));
+    if config.watch && config.store_only {
+        return Err("--watch prompts for a file between runs, so it can't be combined with --store".to_string());
+    }
+    // Flags which only print information don't need a command
+    if command_args.is_empty() && (config.prints_info() || config.last_files) {
+        return Ok((CommandCall::default(), config));
     }
+    // After a --, `ds -- history` runs a program called history
+    let subcommand = match command_args.first().map(|command| command.as_str()).filter(|_| !after_separator) {
+        Some("history") => Some(Subcommand::History(parse_run_number("history", &command_args[1..])?)),
+        Some("replay") => Some(Subcommand::Replay(parse_run_number("replay", &command_args[1..])?)),
+        _ => None,
+    };
+    if subcommand.is_some() {
+        config.subcommand = subcommand;
+        return Ok((CommandCall::default(), config));
+    }
+    if config.rerun_failed {
+        if !command_args.is_empty() {
+            return Err("--rerun-failed reruns the last command, so it can't be given another".to_string());
+        }
+        // The command is read from the history once the arguments have been
+        return Ok((CommandCall::default(), config));
+    }
+    let command = parse_command(command_args)?;
+    detect_command_settings(&mut config, &command);
 
     Ok((command, config))
 }
 
-fn split_config_command(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+// Picks the rules and remaps for the command, unless they were given as flags
+pub fn detect_command_settings(config: &mut Config, command: &CommandCall) {
+    // Rules given as flags replace the ones picked for the command
+    if config.rules.is_empty() {
+        config.rules.extend(Rule::detect(command));
+    }
+    config.remaps.extend(Remap::detect(command));
+}
+
+// Checks if a command is a call to ds, either by name or because it resolves to 
+// this executable, e.g. through an absolute path or a symlink
+fn is_ds_command(command: &str) -> bool {
+    if command == "ds" {
+        return true;
+    }
+
+    let current_exe = match env::current_exe().and_then(fs::canonicalize) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    find_executable(command)
+        .and_then(|path| fs::canonicalize(path).ok())
+        .is_some_and(|path| path == current_exe)
+}
+
+// Finds the file that would be run for a command, searching the PATH for bare names
+fn find_executable(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        return Some(PathBuf::from(command));
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(command))
+        .find(|path| path.is_file())
+}
+
+// Splits the args into the ones for ds and the command to run. The command starts at the 
+// first arg which isn't a flag or a flag's value, or after an explicit `--`, which is
+// returned as whether the command came after one
+fn split_config_command(args: Vec<String>) -> (Vec<String>, Vec<String>, bool) {
+    let flags = Flag::build_flags();
     let mut config = vec!();
-    let mut command = vec!();
-    let mut reading_config = true;
+    let mut args = args.into_iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return (config, args.collect(), true);
+        }
 
-    for arg in args.into_iter().skip(1) {
-        if !arg.starts_with("-") {
-            reading_config = false;
+        if !arg.starts_with('-') {
+            let mut command = vec!(arg);
+            command.extend(args);
+            return (config, command, false);
         }
 
-        if reading_config {
-            config.push(arg);
-        } 
-        else {
-            command.push(arg);
+        // Keep a flag's value with the flag, so it isn't mistaken for the command
+        let needs_value = needs_separate_value(&flags, &arg);
+        config.push(arg);
+        if needs_value {
+            config.extend(args.next());
         }
     }
 
-    (config, command)
+    (config, vec!(), false)
+}
+
+// Checks if a flag takes a value that has been passed as the following arg
+fn needs_separate_value(flags: &[Flag], arg: &str) -> bool {
+    if let Some(arg_name) = arg.strip_prefix("--") {
+        return Flag::find_long(flags, arg_name).is_some_and(|flag| flag.takes_value());
+    }
+
+    let arg_chars = &arg[1..];
+    for (idx, c) in arg_chars.char_indices() {
+        if Flag::find_short(flags, c).is_some_and(|flag| flag.takes_value()) {
+            return arg_chars[idx + c.len_utf8()..].is_empty();
+        }
+    }
+    false
 }
 
 
 fn parse_config(args: Vec<String>) -> Result<Config, String> {
     let mut config = Config::default();
     let flags = Flag::build_flags();
+    let mut args = args.into_iter();
 
-    for arg in args {
+    while let Some(arg) = args.next() {
 
         if arg.starts_with("---") {
             return Err(format!("Invalid argument '{}'. Start parametes with 1 or 2 dashes", arg));
         }
 
-        // Long form args
-        else if arg.starts_with("--") {
-            let arg_name = &arg[2..];
-            let this_flag = flags.iter().find(|flag| flag.long == arg_name);
-            
-            match this_flag {
-                Some(flag) => config.set_flag(&flag),
+        // Long form args, with values given as either `--name=value` or `--name value`
+        else if let Some(arg_name) = arg.strip_prefix("--") {
+            let (arg_name, inline_value) = match arg_name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg_name, None),
+            };
+
+            let flag = match Flag::find_long(&flags, arg_name) {
+                Some(flag) => flag,
                 None => return Err(format!("Invalid parameter '{}' found", arg)),
             };
-        }
 
-        // Short form params
-        else if arg.starts_with("-") {
-            let arg_chars = &arg[1..];
-            for c in arg_chars.chars() {
-                let this_flag = flags.iter().find(|flag| flag.short == c);
+            let value = if flag.takes_value() {
+                match inline_value.or_else(|| args.next()) {
+                    Some(value) => Some(value),
+                    None => return Err(format!("Parameter '--{}' needs a value", flag.long)),
+                }
+            }
+            else if inline_value.is_some() {
+                return Err(format!("Parameter '--{}' doesn't take a value", flag.long));
+            }
+            else {
+                None
+            };
+            config.set_flag(flag, value)?;
+        }
 
-                match this_flag {
-                    Some(flag) => config.set_flag(&flag),
+        // Short form params, a flag taking a value uses the rest of the group or the next arg
+        else if let Some(arg_chars) = arg.strip_prefix("-") {
+            for (idx, c) in arg_chars.char_indices() {
+                let flag = match Flag::find_short(&flags, c) {
+                    Some(flag) => flag,
                     None => return Err(format!("Invalid parameter {} found in group {}", c, arg))
+                };
+
+                if !flag.takes_value() {
+                    config.set_flag(flag, None)?;
+                    continue;
+                }
 
+                let rest = &arg_chars[idx + c.len_utf8()..];
+                let value = if !rest.is_empty() { Some(rest.to_string()) } else { args.next() };
+                match value {
+                    Some(value) => config.set_flag(flag, Some(value))?,
+                    None => return Err(format!("Parameter '-{}' needs a value", c)),
                 };
+                break;
             }
         }
     }
@@ -164,7 +528,7 @@ fn parse_config(args: Vec<String>) -> Result<Config, String> {
 fn parse_command(args: Vec<String>) -> Result<CommandCall, String> {
     let args: Vec<String> = args.into_iter().collect();
 
-    if args.len() < 1 {
+    if args.is_empty() {
         return Err("No command found".to_string());
     }
 
@@ -197,7 +561,7 @@ mod test {
     use super::*;
 
     fn string_args(args: &[&str]) -> Vec<String> {
-        args.into_iter().map(|s| s.to_string()).collect()
+        args.iter().map(|s| s.to_string()).collect()
     }
 
     #[test]
@@ -237,6 +601,89 @@ mod test {
         assert!(config.is_err());
     }
 
+    #[test]
+    fn can_read_long_config_values() {
+        let input = string_args(&["--editor=code", "--rule", "cargo"]);
+        let config = parse_config(input).unwrap();
+
+        assert_eq!(config.editor.as_deref(), Some("code"));
+        assert_eq!(config.rules, vec!(Rule::Cargo));
+    }
+
+    #[test]
+    fn can_read_short_config_values() {
+        let input = string_args(&["-ce", "code"]);
+        let config = parse_config(input).unwrap();
+        assert!(config.no_colour);
+        assert_eq!(config.editor.as_deref(), Some("code"));
+
+        let input = string_args(&["-ecode"]);
+        let config = parse_config(input).unwrap();
+        assert_eq!(config.editor.as_deref(), Some("code"));
+    }
+
+    #[test]
+    fn can_read_sort_order() {
+        let config = parse_config(string_args(&["--sort", "path"])).unwrap();
+        assert_eq!(config.sort, SortOrder::Path);
+
+        assert!(parse_config(string_args(&["--sort=size"])).is_err());
+    }
+
+    #[test]
+    fn can_read_summary_limit() {
+        let config = parse_config(string_args(&["--summary-limit", "5"])).unwrap();
+        assert!(config.summary);
+        assert_eq!(config.summary_limit, Some(5));
+
+        assert!(parse_config(string_args(&["--summary-limit=all"])).is_err());
+    }
+
+    #[test]
+    fn can_read_colour_mode() {
+        let config = parse_config(string_args(&["--color=never"])).unwrap();
+        assert_eq!(config.colour, ColourMode::Never);
+
+        assert!(parse_config(string_args(&["--color", "sometimes"])).is_err());
+    }
+
+    #[test]
+    fn can_read_timeout() {
+        let config = parse_config(string_args(&["--timeout", "90"])).unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_secs(90)));
+
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("99999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn can_read_paging() {
+        let config = parse_config(string_args(&["--paging", "never"])).unwrap();
+        assert_eq!(config.paging, Paging::Never);
+
+        assert!(parse_config(string_args(&["--paging=sometimes"])).is_err());
+    }
+
+    #[test]
+    fn can_repeat_flags() {
+        let input = string_args(&["--rule", "cargo", "-c", "--rule=make", "-c"]);
+        let config = parse_config(input).unwrap();
+
+        assert!(config.no_colour);
+        assert_eq!(config.rules, vec!(Rule::Cargo, Rule::Make));
+    }
+
+    #[test]
+    fn rejects_missing_and_unexpected_values() {
+        assert!(parse_config(string_args(&["--editor"])).is_err());
+        assert!(parse_config(string_args(&["-e"])).is_err());
+        assert!(parse_config(string_args(&["--no-colour=yes"])).is_err());
+    }
+
     #[test]
     fn can_parse_command_with_no_args() {
         let input = string_args(&["vi"]);
@@ -258,6 +705,17 @@ mod test {
         assert_eq!(command.args[0], "a_file.txt");
     }
 
+    #[test]
+    fn can_join_command_for_shell() {
+        let input = string_args(&["make", "2>&1", "|", "grep", "error"]);
+        let command = parse_command(input).unwrap();
+        assert_eq!(command.shell_line(), "make 2>&1 | grep error");
+
+        let input = string_args(&["make 2>&1 | grep error"]);
+        let command = parse_command(input).unwrap();
+        assert_eq!(command.shell_line(), "make 2>&1 | grep error");
+    }
+
     #[test] 
     fn can_parse_whole_line() {
         let input = string_args(&["ds", "-c", "--help", "alr", "build"]);
@@ -275,6 +733,114 @@ mod test {
         assert!(config.print_help);
     }
 
+    #[test]
+    fn can_parse_flag_values_before_command() {
+        let input = string_args(&["ds", "--editor", "code", "--rule", "cargo", "make"]);
+        let (command, config) = read_args(input).unwrap();
+
+        assert_eq!(command.command, "make");
+        assert_eq!(command.args.len(), 0);
+        assert_eq!(config.editor.as_deref(), Some("code"));
+        assert_eq!(config.rules, vec!(Rule::Cargo));
+    }
+
+    #[test]
+    fn detects_rules_for_command() {
+        let input = string_args(&["ds", "cargo", "build"]);
+        let (_command, config) = read_args(input).unwrap();
+        assert_eq!(config.rules, vec!(Rule::Cargo));
+
+        let input = string_args(&["ds", "--rule", "make", "cargo", "build"]);
+        let (_command, config) = read_args(input).unwrap();
+        assert_eq!(config.rules, vec!(Rule::Make));
+
+        assert!(parse_config(string_args(&["--rule", "ant"])).is_err());
+    }
+
+    #[test]
+    fn double_dash_starts_command() {
+        let input = string_args(&["ds", "-c", "--", "--weird-command", "-c"]);
+        let (command, config) = read_args(input).unwrap();
+
+        assert_eq!(command.command, "--weird-command");
+        assert_eq!(command.args, vec!("-c"));
+        assert!(config.no_colour);
+    }
+
+    #[test]
+    fn unwraps_nested_ds_calls() {
+        let input = string_args(&["ds", "-f", "ds", "-c", "cargo", "build"]);
+        let (command, config) = read_args(input).unwrap();
+
+        assert_eq!(command.command, "cargo");
+        assert_eq!(command.args, vec!("build"));
+        assert!(config.files_only);
+        assert!(config.no_colour);
+    }
+
+    #[test]
+    fn unwraps_ds_called_by_path() {
+        let exe = env::current_exe().unwrap().to_string_lossy().into_owned();
+        let input = string_args(&["ds", "-f", &exe, "--no-colour", "cargo", "build"]);
+        let (command, config) = read_args(input).unwrap();
+
+        assert_eq!(command.command, "cargo");
+        assert!(config.files_only);
+        assert!(config.no_colour);
+    }
+
+    #[test]
+    fn rejects_nested_ds_without_command() {
+        let input = string_args(&["ds", "-f", "ds", "-c"]);
+        assert!(read_args(input).is_err());
+    }
+
+    #[test]
+    fn help_does_not_need_command() {
+        let input = string_args(&["ds", "-h"]);
+        let (_command, config) = read_args(input).unwrap();
+        assert!(config.print_help);
+
+        let input = string_args(&["ds", "--completions", "bash"]);
+        let (_command, config) = read_args(input).unwrap();
+        assert_eq!(config.completions.as_deref(), Some("bash"));
+    }
+
+    #[test]
+    fn can_parse_history_subcommand() {
+        let (_command, config) = read_args(string_args(&["ds", "history"])).unwrap();
+        assert_eq!(config.subcommand, Some(Subcommand::History(None)));
+
+        let (_command, config) = read_args(string_args(&["ds", "-c", "history", "3"])).unwrap();
+        assert_eq!(config.subcommand, Some(Subcommand::History(Some(3))));
+
+        assert!(read_args(string_args(&["ds", "history", "0"])).is_err());
+        assert!(read_args(string_args(&["ds", "history", "last"])).is_err());
+
+        let (_command, config) = read_args(string_args(&["ds", "replay"])).unwrap();
+        assert_eq!(config.subcommand, Some(Subcommand::Replay(None)));
+    }
+
+    #[test]
+    fn runs_history_command_after_separator() {
+        let (command, config) = read_args(string_args(&["ds", "--", "history"])).unwrap();
+        assert_eq!(config.subcommand, None);
+        assert_eq!(command.command, "history");
+    }
+
+    #[test]
+    fn runs_replay_command_after_separator() {
+        let (command, config) = read_args(string_args(&["ds", "-s", "--", "replay", "3"])).unwrap();
+        assert_eq!(config.subcommand, None);
+        assert_eq!(command.shell_line(), "replay 3");
+    }
+
+    #[test]
+    fn rejects_storing_only_when_watching() {
+        let result = read_args(string_args(&["ds", "-ws", "cargo", "build"]));
+        assert_eq!(result.err().as_deref(), Some("--watch prompts for a file between runs, so it can't be combined with --store"));
+    }
+
     #[test] 
     fn can_parse_with_no_config() {
         let input = string_args(&["ds", "alr", "build"]);
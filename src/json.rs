@@ -0,0 +1,131 @@
+use crate::file_list::FileList;
+use crate::hyperlink::absolute_path;
+use crate::line_reader::File;
+
+// ====================== Records =========================
+
+// A single captured file, as written to one line in --jsonl mode
+pub fn file_record(file: &File, command: &str) -> String {
+    let mut record = JsonObject::new();
+    record.string("type", "file");
+    record.number("index", Some(file.idx as i64));
+    record.string("path", &absolute_path(&file.name).to_string_lossy());
+    record.number("line", file.line.map(|line| line as i64));
+    record.number("column", file.column.map(|column| column as i64));
+    record.string("stream", file.source.name());
+    record.string("severity", file.severity.name());
+    record.boolean("changed", file.changed);
+    record.boolean("missing", file.missing);
+    record.string("text", &file.message);
+    record.string("command", command);
+    record.finish()
+}
+
+// The final record, written once the command has finished
+pub fn summary_record(files: &FileList, command: &str, exit_status: Option<i32>) -> String {
+    let mut record = JsonObject::new();
+    record.string("type", "summary");
+    record.string("command", command);
+    record.number("exit_status", exit_status.map(i64::from));
+    record.number("file_count", Some(files.len() as i64));
+    record.finish()
+}
+
+// The whole run as a single document for --json mode
+pub fn json_document(files: &FileList, command: &str, exit_status: Option<i32>) -> String {
+    let records: Vec<String> = files.iter().map(|file| file_record(file, command)).collect();
+
+    let mut document = JsonObject::new();
+    document.string("command", command);
+    document.number("exit_status", exit_status.map(i64::from));
+    document.raw("files", &format!("[{}]", records.join(",")));
+    document.finish()
+}
+
+
+// ====================== Helpers =========================
+
+// Builds up a JSON object one field at a time
+struct JsonObject {
+    text: String,
+}
+
+impl JsonObject {
+    fn new() -> JsonObject {
+        JsonObject { text: "{".to_string() }
+    }
+
+    fn raw(&mut self, key: &str, value: &str) {
+        if self.text.len() > 1 {
+            self.text.push(',');
+        }
+        self.text.push_str(&escape_string(key));
+        self.text.push(':');
+        self.text.push_str(value);
+    }
+
+    fn string(&mut self, key: &str, value: &str) {
+        self.raw(key, &escape_string(value));
+    }
+
+    fn number(&mut self, key: &str, value: Option<i64>) {
+        let value = value.map_or("null".to_string(), |value| value.to_string());
+        self.raw(key, &value);
+    }
+
+    fn boolean(&mut self, key: &str, value: bool) {
+        self.raw(key, if value { "true" } else { "false" });
+    }
+
+    fn finish(mut self) -> String {
+        self.text.push('}');
+        self.text
+    }
+}
+
+fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape_string("a \"b\"\\\n\x1B"), "\"a \\\"b\\\"\\\\\\n\\u001b\"");
+    }
+
+    #[test]
+    fn writes_file_record() {
+        let mut file = File::new("/tmp/a.rs".to_string(), Some(3), None);
+        file.idx = 1;
+        file.message = "error: /tmp/a.rs:3".to_string();
+
+        let record = file_record(&file, "cargo build");
+        assert!(record.starts_with("{\"type\":\"file\",\"index\":1,\"path\":\"/tmp/a.rs\",\"line\":3,\"column\":null,"));
+        assert!(record.ends_with("\"text\":\"error: /tmp/a.rs:3\",\"command\":\"cargo build\"}"));
+    }
+
+    #[test]
+    fn writes_summary_record() {
+        let files = FileList::new(Default::default());
+        let record = summary_record(&files, "make", Some(2));
+        assert_eq!(record, "{\"type\":\"summary\",\"command\":\"make\",\"exit_status\":2,\"file_count\":0}");
+    }
+}
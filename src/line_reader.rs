@@ -1,19 +1,40 @@
 
-use std::collections::HashSet;
-use std::sync::mpsc::Receiver;
-use std::hash::{Hash, Hasher};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::io::{self, BufWriter, Write};
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
-use crate::processes::{LineSource, LineMessage};
+use memchr::{memchr, memchr3};
+
+use crate::processes::{LineSource, LineMessage, LineBatch};
 use crate::input_reader::Config;
+use crate::file_list::FileList;
+use crate::severity::{Severity, Classifier};
+use crate::theme::FileKind;
+use crate::hyperlink::{hyperlink, file_url};
+use crate::ignore::IgnoreRules;
+use crate::failures::{failed_test, FailedTest};
+use crate::git::GitStatus;
+use crate::backtrace::resolve_rust_path;
+use crate::remap::remap_path;
+use crate::resolver::Resolver;
 
 // ====================== Constants =========================
 
-const GREEN_TEXT: &'static str = "\x1B[92m";
-const YELLOW_TEXT: &'static str = "\x1B[93m";
-const UNDERLINE_TEXT: &'static str = "\x1B[4m";
-const RESET_TEXT: &'static str = "\x1B[0m";
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+// How many lines can be read ahead of the one being printed, to check their files early
+const MAX_QUEUED_LINES: usize = 256;
+
+// Extensions a missing file can have without a / in its name, as otherwise names like
+// "self.name" or "example.com" would be taken for files
+const KNOWN_EXTENSIONS: &[&str] = &[
+    "c", "h", "cc", "cpp", "hpp", "cs", "go", "rs", "py", "py3", "pyi", "rb", "js", "jsx", "mjs",
+    "ts", "tsx", "java", "kt", "scala", "swift", "php", "lua", "sh", "sql", "html", "css", "scss",
+    "vue", "json", "yaml", "yml", "toml", "xml", "ini", "cfg", "md", "txt", "log", "lock", "mk",
+];
 
 // ====================== Types =========================
 
@@ -22,21 +43,52 @@ pub struct File {
     pub idx: usize,
     pub name: String, 
     pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub hits: usize,
+    pub message: String,
+    pub severity: Severity,
+    pub source: LineSource,
+    // Modified, staged or untracked in git
+    pub changed: bool,
+    // Numbered with --allow-missing, but not found on disk
+    pub missing: bool,
+    // Checked once when the file is found, rather than each time it's shown
+    pub is_dir: bool,
 }
 
-impl PartialEq for File {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.line == other.line
+impl File {
+    // The index is assigned when the file is first added to the FileList
+    pub fn new(name: String, line: Option<usize>, column: Option<usize>) -> File {
+        File { idx: 0, name, line, column, hits: 1, message: String::new(), severity: Severity::Mention,
+               source: LineSource::Out, changed: false, missing: false, is_dir: false }
+    }
+
+    // The name with the line and column if known e.g. "src/main.rs:12:5"
+    pub fn location(&self) -> String {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", self.name, line, column),
+            (Some(line), None) => format!("{}:{}", self.name, line),
+            _ => self.name.clone(),
+        }
+    }
+
+    // Shown after the number, "*" for changed files and "?" for missing ones
+    pub fn marker(&self) -> &'static str {
+        match (self.changed, self.missing) {
+            (true, true) => "*?",
+            (true, false) => "*",
+            (false, true) => "?",
+            (false, false) => "",
+        }
     }
 }
-impl Eq for File {}
 
-impl Hash for File {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
-        self.line.hash(state);
+impl PartialEq for File {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.line == other.line && self.column == other.column
     }
 }
+impl Eq for File {}
 
 
 #[derive(PartialEq, Eq, Debug)]
@@ -44,12 +96,14 @@ enum LinePart<'a> {
     Text(&'a str),
     Candidate(&'a str),
     File(File),
+    // A file that is highlighted, but can't be selected
+    Unnumbered(File),
     Space,
 }
 
 impl<'a> LinePart<'a> {
     fn new(slice: &'a str, is_candidate: bool) -> LinePart<'a> {
-        if slice == "" {
+        if slice.is_empty() {
             LinePart::Space
         } else if is_candidate {
             LinePart::Candidate(slice)
@@ -59,9 +113,15 @@ impl<'a> LinePart<'a> {
     }
 
     pub fn write_file_name(config: &Config, file: &File) -> String {
-        let effect = if config.no_colour { UNDERLINE_TEXT } else { GREEN_TEXT };
-        format!("{effect}[{0}. {1}]{RESET_TEXT}", file.idx, file.name)
+        // Changed files are marked next to the number, as they're the likeliest to be picked
+        let text = format!("[{0}{1}. {2}]", file.idx, file.marker(), file.location());
+        let text = config.theme.paint(FileKind::of(file), &text);
 
+        if config.hyperlinks {
+            hyperlink(&file_url(file, config.link_format.as_deref()), &text)
+        } else {
+            text
+        }
     }
 
     pub fn render(&self, config: &Config) -> Option<Cow<'a, str>> {
@@ -69,7 +129,11 @@ impl<'a> LinePart<'a> {
             LinePart::Text(text) => Some(Cow::Borrowed(text)),
             LinePart::Space => None,
             LinePart::File(file) => {
-                let text = LinePart::write_file_name(config, &file);
+                let text = LinePart::write_file_name(config, file);
+                Some(Cow::Owned(text))
+            }
+            LinePart::Unnumbered(file) => {
+                let text = config.theme.paint(FileKind::of(file), &file.location());
                 Some(Cow::Owned(text))
             }
             LinePart::Candidate(_) => {
@@ -85,30 +149,93 @@ impl<'a> LinePart<'a> {
 // ====================== Main Function =========================
 
 
-pub fn process_streams(config: &Config, rx: Receiver<LineMessage>) -> HashSet<File> {
+// Reads and prints the output to `out`, numbering the files found in it. 
+// `on_file` is called with each file the first time it's seen
+pub fn process_streams<W, F>(config: &Config, rx: &Receiver<LineBatch>, out: W, mut on_file: F) -> FileList
+where
+    W: Write,
+    F: FnMut(&File)
+{
     let mut stdout_closed = false;
     let mut stderr_closed = false;
 
-    let mut files = HashSet::new();
-    while !(stderr_closed && stdout_closed) {
-        let message = rx.recv().unwrap();
+    let mut files = FileList::new(config.numbering);
+    let mut classifier = Classifier::new(&config.rules);
+    let ignore = IgnoreRules::from_config(config);
+    let resolver = Resolver::new(config, GitStatus::load());
+    let mut out = BufWriter::with_capacity(WRITE_BUFFER_SIZE, out);
+
+    thread::scope(|scope| {
+        // Candidates in the lines waiting to be printed are checked on a worker in the meantime
+        let (batches, batch_rx) = mpsc::channel();
+        let worker_resolver = &resolver;
+        scope.spawn(move || worker_resolver.work(batch_rx));
+        let mut queued = VecDeque::new();
+
+        while !(stderr_closed && stdout_closed) {
+            let mut received = vec!();
+            if queued.is_empty() {
+                // Output is only flushed once there's nothing more to print, to keep up with
+                // commands printing quickly without leaving slow ones unprinted
+                received = rx.try_recv().unwrap_or_else(|_| {
+                    let _ = out.flush();
+                    rx.recv().unwrap()
+                });
+            }
+            while queued.len() + received.len() < MAX_QUEUED_LINES {
+                match rx.try_recv() {
+                    Ok(batch) => received.extend(batch),
+                    Err(_) => break,
+                }
+            }
 
-        if message.close_stream {
-            match message.source {
-                LineSource::Out => stdout_closed = true,
-                LineSource::Error => stderr_closed = true,
-            };
-        }
+            if !received.is_empty() {
+                let claimed = resolver.claim(received.iter().flat_map(|message| candidates(&message.line)));
+                if !claimed.is_empty() {
+                    let _ = batches.send(claimed);
+                }
+                queued.extend(received);
+            }
+            let message = queued.pop_front().unwrap();
 
-        // Avoid printing an empty line for the end of file
-        if message.close_stream && message.line.is_empty() {
-            continue;
-        }
+            if message.close_stream {
+                match message.source {
+                    LineSource::Out => stdout_closed = true,
+                    LineSource::Error => stderr_closed = true,
+                };
+            }
 
-        let line_parts = parse_line(&message.line);
-        print_line_parts(config, &mut files, line_parts);
+            // Avoid printing an empty line for the end of file
+            if message.close_stream && message.line.is_empty() {
+                continue;
+            }
 
-    }
+            for rule in &config.rules {
+                if let Some(id) = failed_test(*rule, &message.line) {
+                    files.add_failed_test(FailedTest { rule: *rule, id });
+                }
+            }
+
+            // Only a line which could have a file in it needs classifying
+            let severity = if memchr(b'.', message.line.as_bytes()).is_some() {
+                classifier.classify(&message.line, message.source)
+            } else {
+                classifier.observe(&message.line);
+                Severity::Mention
+            };
+            let line_parts = LineTokens::new(&message.line)
+                .map(|part| check_if_file_exists(config, &resolver, &ignore, &mut files, part, &message, severity))
+                .inspect(|part| if let LinePart::File(file) = part { on_file(file) });
+
+            if config.shows_output() {
+                // Like println, a closed stdout doesn't stop the files being read
+                let _ = write_line_parts(&mut out, config, line_parts);
+            } else {
+                line_parts.for_each(drop);
+            }
+        }
+    });
+    let _ = out.flush();
     files
 }
 
@@ -116,75 +243,96 @@ pub fn process_streams(config: &Config, rx: Receiver<LineMessage>) -> HashSet<Fi
 // ====================== Helpers =========================
 
 
-// Parse a file and break it into a list of line parts
-fn parse_line<'a>(line: &'a str) -> Vec<LinePart<'a>> {
-    let mut parts = vec!();
-
-    let mut start_idx = 0;
-    let mut is_candiate = false;
-
-    for (idx, byte) in line.bytes().enumerate() {
-        // A space indicates the end of a token 
-        // TODO: find a better way to do this then comparing to magic numbers
-        if [32, 13, 10].contains(&byte) {
-            let slice = &line[start_idx..idx];
-            let line_part = LinePart::new(slice, is_candiate);
-
-            parts.push(line_part);
-            start_idx = idx + 1;
-            is_candiate = false;
-        } 
+// The tokens in a line that could be files
+fn candidates(line: &str) -> impl Iterator<Item = &str> {
+    LineTokens::new(line).filter_map(|part| match part {
+        LinePart::Candidate(name) => Some(name),
+        _ => None,
+    })
+}
 
-        // A dot in a token indicates a potential file
-        else if byte == 46 {
-            is_candiate = true;
-        }
-    }
+// Breaks a line into parts at each space or line ending, without allocating
+struct LineTokens<'a> {
+    line: &'a str,
+    start_idx: usize,
+}
 
-    // Capture the final token 
-    let slice = &line[start_idx..];
-    if slice != "" {
-        let line_part = LinePart::new(slice, is_candiate);
-        parts.push(line_part);
+impl<'a> LineTokens<'a> {
+    fn new(line: &'a str) -> LineTokens<'a> {
+        LineTokens { line, start_idx: 0 }
     }
-
-    parts
 }
 
+impl<'a> Iterator for LineTokens<'a> {
+    type Item = LinePart<'a>;
 
+    fn next(&mut self) -> Option<LinePart<'a>> {
+        let rest = self.line.get(self.start_idx..)?;
 
-fn print_line_parts(config: &Config, files: &mut HashSet<File>, line: Vec<LinePart>) {
-    let parts = line.into_iter().map(|part| check_if_file_exists(files, part));
+        let slice = match memchr3(b' ', b'\r', b'\n', rest.as_bytes()) {
+            Some(end_idx) => {
+                self.start_idx += end_idx + 1;
+                &rest[..end_idx]
+            }
+            // Capture the final token 
+            None => {
+                self.start_idx = self.line.len() + 1;
+                if rest.is_empty() {
+                    return None;
+                }
+                rest
+            }
+        };
+
+        // A dot in a token indicates a potential file
+        let is_candidate = memchr(b'.', slice.as_bytes()).is_some();
+        Some(LinePart::new(slice, is_candidate))
+    }
+}
 
-    let mut output = String::new();
-    for part in parts {
-        let text = part.render(config);
 
-        if output.len() != 0 {
-            output.push_str(" ");
+// Writes the parts separated by spaces, leaving out any spaces before the first part
+fn write_line_parts<'a, W: Write>(
+    out: &mut W, 
+    config: &Config, 
+    line: impl Iterator<Item = LinePart<'a>>,
+) -> io::Result<()> {
+    let mut is_empty = true;
+    for part in line {
+        if !is_empty {
+            out.write_all(b" ")?;
         }
 
-        if let Some(text) = text {
-            output.push_str(&text);
+        if let Some(text) = part.render(config) {
+            out.write_all(text.as_bytes())?;
+            is_empty = false;
         }
     }
-    println!("{}", output);
+    out.write_all(b"\n")
 }
 
-fn path_exists(name: &str) -> Option<String> {
+fn path_exists(config: &Config, name: &str) -> Option<String> {
+    // Remaps come first, as the path in a container can also exist on the host
+    let name = remap_path(&config.remaps, name);
+    let name = name.as_ref();
+
     if Path::new(&name).exists() {
+        // "./src/main.rs" is the same file as "src/main.rs", as printed in backtraces
+        let name = name.strip_prefix("./").filter(|rest| !rest.is_empty()).unwrap_or(name);
         Some(name.to_string())
     } else {
-        None
+        resolve_rust_path(name).map(|path| path.to_string_lossy().into_owned())
     }
 }
 
 // THis checks if the filename exists, and also applies a few common heuristics 
 // to look for common patterns of printing names
-fn check_file_exists(name: &str) -> Option<String> {
+fn check_file_exists(config: &Config, name: &str) -> Option<File> {
 
-    let file_path = if let Some(name) = path_exists(name) {
-        Some(name)
+    if let Some(name) = path_exists(config, name) {
+        let mut file = File::new(name, None, None);
+        file.is_dir = Path::new(&file.name).is_dir();
+        Some(file)
     }
 
     // Parenthesized e.g. (filename.txt): as in dbt
@@ -192,26 +340,113 @@ fn check_file_exists(name: &str) -> Option<String> {
     // this won't check any subsequent branches
     else if name.starts_with("(") && name.ends_with(")") {
         let stripped_name = &name[1..(name.len()-1)];
-        path_exists(stripped_name)
+        check_file_exists(config, stripped_name)
     }
 
+    // With a line and column e.g. src/main.rs:12:5: as in most compilers
     else {
-        None
-    };
+        match split_location(name) {
+            (stripped_name, Some(line), column) => {
+                path_exists(config, stripped_name).map(|name| File::new(name, Some(line), column))
+            }
+            _ => None,
+        }
+    }
+}
+
+// A path that looks like a file, but doesn't exist e.g. "would create src/new.rs"
+fn missing_file(config: &Config, name: &str) -> Option<File> {
+    let (name, line, column) = split_location(name);
+    // Without a file to check, punctuation at the end of a sentence could be part of the name
+    let name = remap_path(&config.remaps, name.trim_end_matches([':', ',', ';', '.']));
+    let name = name.strip_prefix("./").unwrap_or(&name);
+
+    if !looks_like_path(name) {
+        return None;
+    }
+    let mut file = File::new(name.to_string(), line, column);
+    file.missing = true;
+    Some(file)
+}
+
+// Checks for a name with only path characters, ending in an extension like ".rs" or ".py3",
+// and either in a directory or with an extension used for source files. Sentences ("e.g."),
+// numbers ("1.5") and domains ("example.com") are left out
+fn looks_like_path(name: &str) -> bool {
+    let is_path_char = |c: char| c.is_ascii_alphanumeric() || "/._-+~@".contains(c);
+    if name.contains("//") || name.starts_with('-') || !name.chars().all(is_path_char) {
+        return false;
+    }
+
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) => {
+            // Single letters are more likely abbreviations, as in "e.g" or "i.e"
+            !stem.is_empty() && (1..=6).contains(&extension.len())
+                && (stem.len() > 1 || extension.len() > 1 || name.contains('/'))
+                && extension.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+                && extension.bytes().any(|b| b.is_ascii_lowercase())
+                && (name.contains('/') || KNOWN_EXTENSIONS.contains(&extension))
+        }
+        None => false,
+    }
+}
+
+// Splits a trailing line and column number off a name e.g. "src/main.rs:12:5:"
+fn split_location(name: &str) -> (&str, Option<usize>, Option<usize>) {
+    let mut rest = name.trim_end_matches([':', ',']);
+    let mut numbers = vec!();
+
+    while numbers.len() < 2 {
+        match rest.rsplit_once(':') {
+            Some((head, tail)) if !tail.is_empty() && tail.bytes().all(|b| b.is_ascii_digit()) => {
+                numbers.push(tail.parse().ok());
+                rest = head;
+            }
+            _ => break,
+        }
+    }
+
+    match numbers.as_slice() {
+        [column, line] => (rest, *line, *column),
+        [line] => (rest, *line, None),
+        _ => (name, None, None),
+    }
+}
 
-    file_path
+// Finds the file a candidate refers to, or one that's missing if they're allowed
+pub fn find_file(config: &Config, name: &str) -> Option<File> {
+    check_file_exists(config, name)
+        .or_else(|| if config.allow_missing { missing_file(config, name) } else { None })
 }
 
-fn check_if_file_exists<'a>(files: &mut HashSet<File>, raw_part: LinePart<'a>) -> LinePart<'a> {
+fn check_if_file_exists<'a>(
+    config: &Config, 
+    resolver: &Resolver,
+    ignore: &IgnoreRules,
+    files: &mut FileList, 
+    raw_part: LinePart<'a>, 
+    message: &LineMessage, 
+    severity: Severity,
+) -> LinePart<'a> {
     // Check candidates in the line and return 
     match raw_part {
         LinePart::Candidate(name) => {
-            if let Some(path) = check_file_exists(name) {
-            let file = File { idx: files.len() + 1, name: path, line: None };
-                if !files.contains(&file) {
-                    files.insert(file.clone());
-                    return LinePart::File(file);
-                } 
+            if let Some(mut file) = resolver.find(name) {
+                file.message = message.line.trim_end_matches(['\n', '\r']).to_string();
+                file.severity = severity;
+                file.source = message.source;
+
+                // Ignored files are still highlighted, so it's clear they were found
+                let ignored = if file.is_dir { ignore.is_ignored_dir(&file.name) } else { ignore.is_ignored(&file.name) };
+                if (config.errors_only && severity != Severity::Error) || ignored {
+                    return LinePart::Unnumbered(file);
+                }
+
+                // Repeated files are counted, but only the first one is numbered
+                if let Some(file) = files.add(file) {
+                    return LinePart::File(file.clone());
+                }
             }
             LinePart::Text(name)
 
@@ -228,6 +463,21 @@ fn check_if_file_exists<'a>(files: &mut HashSet<File>, raw_part: LinePart<'a>) -
 mod tests {
     use super::*;
 
+    fn parse_line(line: &str) -> Vec<LinePart<'_>> {
+        LineTokens::new(line).collect()
+    }
+
+    fn message(line: &str, source: LineSource) -> LineMessage {
+        LineMessage { line: line.to_string(), source, close_stream: false }
+    }
+
+    // Checks a candidate found in the message, with the ignore rules from the config
+    fn check<'a>(config: &Config, files: &mut FileList, candidate: &'a str, message: &LineMessage, severity: Severity) -> LinePart<'a> {
+        let resolver = Resolver::new(config, GitStatus::default());
+        let ignore = IgnoreRules::from_config(config);
+        check_if_file_exists(config, &resolver, &ignore, files, LinePart::Candidate(candidate), message, severity)
+    }
+
     #[test]
     fn parser_handles_single_token() {
         let line = "token ".to_string();
@@ -269,6 +519,108 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn splits_line_and_column() {
+        assert_eq!(split_location("src/main.rs:12:5:"), ("src/main.rs", Some(12), Some(5)));
+        assert_eq!(split_location("src/main.rs:12"), ("src/main.rs", Some(12), None));
+        assert_eq!(split_location("src/main.rs,"), ("src/main.rs,", None, None));
+        assert_eq!(split_location("a:b.rs:3"), ("a:b.rs", Some(3), None));
+    }
+
+    #[test]
+    fn finds_files_with_locations() {
+        let file = check_file_exists(&Config::default(), "Cargo.toml:3:1:").unwrap();
+        assert_eq!(file.name, "Cargo.toml");
+        assert_eq!(file.line, Some(3));
+        assert_eq!(file.column, Some(1));
+
+        assert!(check_file_exists(&Config::default(), "not_a_file.rs:3").is_none());
+    }
+
+    #[test]
+    fn finds_backtrace_frames() {
+        let file = check_file_exists(&Config::default(), "./src/main.rs:12:5").unwrap();
+        assert_eq!(file.location(), "src/main.rs:12:5");
+    }
+
+    #[test]
+    fn numbers_missing_files_when_allowed() {
+        let message = message("would create src/new_file.rs", LineSource::Out);
+        let mut files = FileList::new(Default::default());
+        let candidate = "src/new_file.rs:3";
+
+        assert_eq!(check(&Config::default(), &mut files, candidate, &message, Severity::Mention), LinePart::Text(candidate));
+
+        let config = Config { allow_missing: true, ..Config::default() };
+        match check(&config, &mut files, candidate, &message, Severity::Mention) {
+            LinePart::File(file) => {
+                assert!(file.missing);
+                assert_eq!(file.location(), "src/new_file.rs:3");
+            }
+            other => panic!("Expected a missing file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_plausible_paths_can_be_missing() {
+        assert!(looks_like_path("src/new.rs"));
+        assert!(looks_like_path("deleted.py3"));
+        assert!(!looks_like_path("e.g."));
+        assert!(!looks_like_path("e.g"));
+        assert!(!looks_like_path("1.5"));
+        assert!(!looks_like_path("https://example.com"));
+        assert!(!looks_like_path("Mr.Smith"));
+        assert!(!looks_like_path("self.name"));
+        assert!(!looks_like_path("example.com"));
+        assert!(!looks_like_path("1.2.3"));
+        assert!(looks_like_path("docs/self.name"));
+    }
+
+    #[test]
+    fn counts_repeated_files() {
+        let config = Config::default();
+        let mut files = FileList::new(Default::default());
+        let message = message("Cargo.toml", LineSource::Error);
+        let first = check(&config, &mut files, "Cargo.toml", &message, Severity::Mention);
+        let second = check(&config, &mut files, "Cargo.toml", &message, Severity::Mention);
+
+        assert!(matches!(first, LinePart::File(File { idx: 1, .. })));
+        assert_eq!(second, LinePart::Text("Cargo.toml"));
+        assert_eq!(files.get(1).unwrap().hits, 2);
+        assert_eq!(files.get(1).unwrap().source, LineSource::Error);
+    }
+
+    #[test]
+    fn errors_only_skips_numbering_other_files() {
+        let config = Config { errors_only: true, ..Config::default() };
+        let mut files = FileList::new(Default::default());
+        let message = message("Cargo.toml", LineSource::Out);
+
+        let warning = check(&config, &mut files, "Cargo.toml", &message, Severity::Warning);
+        let error = check(&config, &mut files, "Cargo.toml", &message, Severity::Error);
+
+        assert!(matches!(warning, LinePart::Unnumbered(_)));
+        assert!(matches!(error, LinePart::File(File { idx: 1, .. })));
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn ignores_directories_by_directory_patterns() {
+        let config = Config { ignore: vec!("src/".to_string()), ..Config::default() };
+        let mut files = FileList::new(Default::default());
+        let dir = check(&config, &mut files, "src", &message("src", LineSource::Out), Severity::Mention);
+        assert!(matches!(dir, LinePart::Unnumbered(File { is_dir: true, .. })));
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn writes_parts_like_the_line() {
+        let config = Config::default();
+        let mut out = vec!();
+        write_line_parts(&mut out, &config, LineTokens::new("  a  b \r\n")).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a  b  \n");
+    }
+
     #[test]
     fn parser_handles_multiple_spaces() {
         let line = "token  token".to_string();
@@ -3,18 +3,52 @@
 
 use std::env;
 use std::collections::HashSet;
-use std::io::{stdin, stdout, Read, Write, BufReader, BufRead};
+use std::io::{stdin, stdout, Read, Write, BufReader, BufRead, IsTerminal};
 use std::path::{PathBuf, Path};
 use std::process::Command;
+use std::sync::mpsc::Receiver;
 
 mod processes;
 mod line_reader;
 mod input_reader;
+mod help;
+mod summary;
+mod file_list;
+mod severity;
+mod theme;
+mod hyperlink;
+mod json;
+mod ignore;
+mod git;
+mod backtrace;
+mod remap;
+mod resolver;
+mod signals;
+mod watch;
+mod failures;
+mod history;
+mod recording;
+mod pager;
 
 
-use crate::processes::run_command;
+use crate::processes::{run_command, run_in_shell, read_log_file};
 use crate::line_reader::{process_streams, File};
-use crate::input_reader::{read_args, CommandCall, Config};
+use crate::file_list::FileList;
+use crate::input_reader::{detect_command_settings, read_args, CommandCall, Config, Flag, OutputFormat, Subcommand};
+use crate::help::{help_text, man_page, completions};
+use crate::summary::{print_changed_files, print_files_only, print_summary};
+use crate::json::{file_record, summary_record, json_document};
+use crate::signals::Timeout;
+use crate::ignore::IgnoreRules;
+use crate::watch::{Wake, Watcher};
+use crate::history::{history_lines, new_run_id, now, project_root, project_runs, StoredRun};
+use crate::recording::{replay, Recorder};
+use crate::pager::Pager;
+use crate::processes::LineBatch;
+use crate::severity::Rule;
+
+// Moves the cursor to the top left after clearing the terminal
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
 
 fn main() {
     match run() {
@@ -25,26 +59,307 @@ fn main() {
 
 fn run() -> Result<(), String> {
     let args = env::args().collect();
-    let (command, config) = read_args(args)?;
-    let files = execute_command_and_read_files(&config, command)?;
+    let (mut command, mut config) = read_args(args)?;
 
-    if files.len() != 0 {
-        let file_num = read_file_number(files.len())?;
-        open_file(&files, file_num)?;
+    if config.prints_info() {
+        return print_info(&config);
     }
-    else {
+
+    match config.subcommand {
+        Some(Subcommand::History(run)) => return show_history(&config, run),
+        Some(Subcommand::Replay(run)) => return replay_run(&mut config, run),
+        None => (),
+    }
+    if config.last_files {
+        return pick_stored_file(&config, &StoredRun::latest()?);
+    }
+    if config.rerun_failed {
+        command = StoredRun::latest_in_project()?.rerun_failed()?;
+        detect_command_settings(&mut config, &command);
+    }
+    if config.watch {
+        return watch_command(&config, &command);
+    }
+
+    let run_id = new_run_id();
+    let (files, exit_status) = execute_command_and_read_files(&config, &command, &run_id)?;
+    check_stopped()?;
+    // Storing is best effort, as not being able to shouldn't get in the way of picking a file
+    let _ = StoredRun::new(run_id, &command, &files, exit_status).save();
+
+    show_files(&config, &command, &files, exit_status)
+}
+
+// Reports the files found once the output has been read, and prompts for one to open
+fn show_files(config: &Config, command: &CommandCall, files: &FileList, exit_status: Option<i32>) -> Result<(), String> {
+    // Machine readable output is used by other tools, so there's no prompt
+    match config.format {
+        OutputFormat::Json => {
+            println!("{}", json_document(files, &command.shell_line(), exit_status));
+            return Ok(());
+        }
+        OutputFormat::JsonLines => {
+            println!("{}", summary_record(files, &command.shell_line(), exit_status));
+            return Ok(());
+        }
+        OutputFormat::Text => (),
+    }
+
+    report_files(config, command, files);
+
+    if !files.is_empty() && !config.store_only {
+        let file_num = read_file_number(files)?;
+        open_file(config, files, file_num)?;
+    }
+    Ok(())
+}
+
+// Ctrl-C still leads to the prompt, but being told to terminate or losing the terminal means
+// nobody is there to pick a file
+fn check_stopped() -> Result<(), String> {
+    match signals::received().filter(|signal| *signal != libc::SIGINT) {
+        Some(signal) => Err(format!("Stopped by {}", signals::signal_name(signal))),
+        None => Ok(()),
+    }
+}
+
+// Prints what's shown after the command's output, before the prompt
+fn report_files(config: &Config, command: &CommandCall, files: &FileList) {
+    if let Some(limit) = config.timeout.filter(|_| signals::timed_out()) {
+        println!("Stopped '{}' after the {:?} timeout", command.shell_line(), limit);
+    }
+
+    if config.files_only {
+        print_files_only(config, files);
+    }
+    if config.summary {
+        print_summary(config, files);
+    }
+    // The summaries already put changed files first
+    if !(config.summary || config.files_only || config.store_only) {
+        print_changed_files(config, files);
+    }
+
+    if files.is_empty() {
         println!("No files found in output");
     }
+}
+
+
+// Lists the runs stored for the current project, or picks a file from one of them
+fn show_history(config: &Config, run: Option<usize>) -> Result<(), String> {
+    let runs = project_runs()?;
+    match run {
+        Some(run) => {
+            let run = runs.get(run - 1).ok_or(format!("{}, is not a run in this project's history", run))?;
+            pick_stored_file(config, run)
+        }
+        None if runs.is_empty() => {
+            println!("No runs stored for this project");
+            Ok(())
+        }
+        None => {
+            for line in history_lines(&runs, &project_root()?, now()) {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+    }
+}
+
+// Shows the recorded output of a run again, finding the files in it afresh so the ones
+// created since are found too
+fn replay_run(config: &mut Config, run: Option<usize>) -> Result<(), String> {
+    let runs = project_runs()?;
+    let run = match run {
+        Some(run) => runs.get(run - 1).ok_or(format!("{}, is not a run in this project's history", run))?,
+        None => runs.iter().find(|run| run.recording().is_some())
+            .ok_or("No recorded runs for this project, use --record to record one".to_string())?,
+    };
+    let recording = run.recording()
+        .ok_or(format!("'{}' wasn't recorded, use --record to record it", run.command.shell_line()))?;
+    let lines = replay(&recording)
+        .map_err(|err| format!("Failed to read recording: '{}', {}", recording.display(), err))?;
+
+    // Files are found relative to where the command ran, using its rules
+    env::set_current_dir(&run.dir)
+        .map_err(|err| format!("Failed to change to '{}', {}", run.dir.display(), err))?;
+    if config.rules.is_empty() {
+        config.rules.extend(Rule::detect(&run.command));
+    }
+
+    let files = read_files(config, &lines, &run.command.shell_line(), start_pager(config, None));
+    show_files(config, &run.command, &files, run.exit_status)
+}
+
+// Numbers the files from a stored run again, so one can be picked without rerunning it
+fn pick_stored_file(config: &Config, run: &StoredRun) -> Result<(), String> {
+    let files = run.file_list();
+    if files.is_empty() {
+        println!("No files found in the run of '{}'", run.command.shell_line());
+        return Ok(());
+    }
+
+    print_summary(config, &files);
+    if !config.store_only {
+        let file_num = read_file_number(&files)?;
+        open_file(config, &files, file_num)?;
+    }
     Ok(())
 }
 
 
-// Run a command an extract a list of files
-fn execute_command_and_read_files(config: &Config, command: CommandCall) -> Result<HashSet<File>, String> {
-    let stream = run_command(&command)
-        .map_err(|err| format!("Failed to start process: '{}', {}", command.command, err))?;
-    let file_set = process_streams(&config, stream);
-    Ok(file_set)
+// Reruns the command whenever a file under the current directory changes. The prompt stays 
+// open between runs, so files can be opened while waiting for the next change
+fn watch_command(config: &Config, command: &CommandCall) -> Result<(), String> {
+    if config.log_file {
+        return Err("Log files can't be watched, use --tail to follow them instead".to_string());
+    }
+    if config.format != OutputFormat::Text {
+        return Err("Watch mode can only print text".to_string());
+    }
+
+    let root = env::current_dir().map_err(|err| format!("Failed to read current directory: {}", err))?;
+    let mut watcher = Watcher::new(&root, IgnoreRules::for_watching(config))
+        .map_err(|err| format!("Failed to watch for changes: {}", err))?;
+    // Only the latest run is kept in the history, so watching doesn't push out every other run
+    let mut last_run_id: Option<String> = None;
+
+    loop {
+        if stdout().is_terminal() {
+            print!("{}", CLEAR_SCREEN);
+        }
+        let run_id = new_run_id();
+        let (files, exit_status) = execute_command_and_read_files(config, command, &run_id)?;
+        check_stopped()?;
+        if let Some(id) = last_run_id.replace(run_id.clone()) {
+            StoredRun::remove(&id);
+        }
+        let _ = StoredRun::new(run_id, command, &files, exit_status).save();
+        // Files saved while the command ran start another run straight away. Ignored files,
+        // like its build output, don't
+        let changed = watcher.take_changes();
+        report_files(config, command, &files);
+        if changed {
+            continue;
+        }
+
+        loop {
+            print!("Enter a file number, or save a file to rerun: ");
+            let _ = stdout().flush();
+
+            let input = match watcher.wait() {
+                Ok(Wake::Changed) => break,
+                Ok(Wake::Input(input)) => input,
+                Err(err) => return Err(format!("Failed to watch for changes: {}", err)),
+            };
+            let opened = parse_file_number(&files, &input)
+                .and_then(|file_num| open_file(config, &files, file_num));
+            if let Err(message) = opened {
+                println!("{}", message);
+            }
+        }
+    }
+}
+
+
+// Prints the help text, man page or completion script
+fn print_info(config: &Config) -> Result<(), String> {
+    let flags = Flag::build_flags();
+
+    if config.print_help {
+        print!("{}", help_text(&flags));
+    }
+    else if config.print_man {
+        print!("{}", man_page(&flags));
+    }
+    else if let Some(shell) = &config.completions {
+        print!("{}", completions(shell, &flags)?);
+    }
+    Ok(())
+}
+
+
+// Run a command an extract a list of files, along with the command's exit code. With --record
+// the output is recorded under the id the run will be stored with
+fn execute_command_and_read_files(config: &Config, command: &CommandCall, run_id: &str) -> Result<(FileList, Option<i32>), String> {
+    let mut output = if config.log_file {
+        // The log file is passed in the place of the command
+        if !command.args.is_empty() {
+            return Err("Only one log file can be read at a time".to_string());
+        }
+        read_log_file(&command.command, config.tail)
+            .map_err(|err| format!("Failed to read log file: '{}', {}", command.command, err))?
+    }
+    else if config.shell {
+        run_in_shell(command, config.aliases)
+            .map_err(|err| format!("Failed to start shell for: '{}', {}", command.shell_line(), err))?
+    }
+    else {
+        run_command(command)
+            .map_err(|err| format!("Failed to start process: '{}', {}", command.command, err))?
+    };
+
+    let recording = if config.record {
+        let path = StoredRun::recording_path(run_id)?;
+        let recording = Recorder::create(&path)
+            .and_then(|recorder| output.record(recorder))
+            .map_err(|err| format!("Failed to record output to '{}', {}", path.display(), err))?;
+        Some(recording)
+    } else {
+        None
+    };
+
+    // Signals are passed on to the command while it runs, then put back for the prompt
+    signals::forward_to(output.process_group());
+    let timeout = config.timeout.map(|limit| Timeout::start(output.process_group(), limit));
+
+    let file_set = read_files(config, &output.lines, &command.shell_line(), start_pager(config, output.process_group()));
+    let exit_status = output.wait();
+    // The end of the output is only written once the recording has finished
+    if let Some(recording) = recording {
+        let _ = recording.join();
+    }
+
+    if let Some(timeout) = timeout {
+        timeout.finish();
+    }
+    signals::stop_forwarding();
+
+    // Compressed log files are read through gzip or zstd, which fail on a corrupt file
+    if let Some(code) = exit_status.filter(|code| config.log_file && *code != 0) {
+        return Err(format!("Failed to decompress log file: '{}', exited with {}", command.command, code));
+    }
+    Ok((file_set, exit_status))
+}
+
+
+// Output is paged when it's printed, other than in watch mode, which reads from stdin between 
+// runs, or when following a log file, which would keep running once the pager is quit
+fn start_pager(config: &Config, group: Option<u32>) -> Option<Pager> {
+    if !config.shows_output() || config.watch || config.tail {
+        return None;
+    }
+    Pager::start(config.paging, group)
+}
+
+// Prints the output, through the pager if there is one, and finds the files in it
+fn read_files(config: &Config, lines: &Receiver<LineBatch>, command_line: &str, mut pager: Option<Pager>) -> FileList {
+    let on_file = |file: &File| {
+        if config.format == OutputFormat::JsonLines {
+            println!("{}", file_record(file, command_line));
+        }
+    };
+    let files = match pager.as_mut().and_then(|pager| pager.input()) {
+        Some(input) => process_streams(config, lines, input, on_file),
+        None => process_streams(config, lines, stdout().lock(), on_file),
+    };
+
+    // The prompt comes after the pager is quit
+    if let Some(pager) = pager {
+        pager.wait();
+    }
+    files
 }
 
 
@@ -57,12 +372,14 @@ fn read_input(prompt: &str) -> String {
 }
 
 
-fn read_file_number(max_n: usize) -> Result<usize, String> {
-    let input = read_input("Enter a file number: ");
+fn read_file_number(files: &FileList) -> Result<usize, String> {
+    parse_file_number(files, &read_input("Enter a file number: "))
+}
 
+fn parse_file_number(files: &FileList, input: &str) -> Result<usize, String> {
     match input.trim().parse::<usize>() {
         Ok(n) => {
-            if n > max_n {
+            if n == 0 || n > files.len() {
                 Err(format!("{}, is not a valid file number", n))
             } 
             else {
@@ -70,22 +387,26 @@ fn read_file_number(max_n: usize) -> Result<usize, String> {
             }
         }
         Err(_) => {
-                Err(format!("{}, is not a number", input))
+                Err(format!("'{}', is not a number", input.trim()))
         }
     }
 }
 
 
-fn open_file(files: &HashSet<File>, file_num: usize) -> Result<(), String> {
-    let file = files.into_iter().find(|f| f.idx == file_num).unwrap();
-
-    let mut cmd = Command::new("nvim");
+fn open_file(config: &Config, files: &FileList, file_num: usize) -> Result<(), String> {
+    let file = files.get(file_num)
+        .ok_or(format!("{}, is not a valid file number", file_num))?;
 
-    if let Ok(home_dir) = std::env::var("HOME") {
-        let server_path = Path::new(&home_dir).join(".config/nvim/server.pipe");
-        cmd.arg("--server")
-           .arg(server_path)
-           .arg("--remote");
+    let mut cmd = match &config.editor {
+        // The editor can include its own arguments, e.g. "code --wait"
+        Some(editor) => {
+            let mut parts = editor.split_whitespace();
+            let program = parts.next().ok_or("No editor command given".to_string())?;
+            let mut cmd = Command::new(program);
+            cmd.args(parts);
+            cmd
+        }
+        None => nvim_command(config),
     };
 
     let out = cmd.arg(file.name.clone()).status();
@@ -95,3 +416,19 @@ fn open_file(files: &HashSet<File>, file_num: usize) -> Result<(), String> {
         Err(err) => Err(format!("Failed to open file: {}", err)),
     }
 }
+
+fn nvim_command(config: &Config) -> Command {
+    let mut cmd = Command::new("nvim");
+
+    if config.open_here {
+        return cmd;
+    }
+
+    if let Ok(home_dir) = std::env::var("HOME") {
+        let server_path = Path::new(&home_dir).join(".config/nvim/server.pipe");
+        cmd.arg("--server")
+           .arg(server_path)
+           .arg("--remote");
+    };
+    cmd
+}
@@ -0,0 +1,79 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// ====================== Types =========================
+
+// When the output is shown through a pager
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum Paging {
+    // Only when stdout is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Paging {
+    pub fn parse(value: &str) -> Result<Paging, String> {
+        match value {
+            "auto" => Ok(Paging::Auto),
+            "always" => Ok(Paging::Always),
+            "never" => Ok(Paging::Never),
+            _ => Err(format!("Invalid paging mode '{}', expected auto, always or never", value)),
+        }
+    }
+}
+
+// Shows output a screen at a time through $DS_PAGER, $PAGER or `less -RFX`, which keeps
+// the highlighting and exits straight away if the output fits on one screen
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    // The pager joins the command's process group, if there is one, as the command is given 
+    // the terminal while it runs and the pager needs to read keys from it too
+    pub fn start(paging: Paging, group: Option<u32>) -> Option<Pager> {
+        match paging {
+            Paging::Never => return None,
+            Paging::Auto if !io::stdout().is_terminal() => return None,
+            _ => (),
+        }
+
+        let pager = ["DS_PAGER", "PAGER"].iter()
+            .find_map(|name| env::var(name).ok().filter(|pager| !pager.trim().is_empty()))
+            .unwrap_or_else(|| "less -RFX".to_string());
+        // The pager can include its own arguments, e.g. "less -S"
+        let mut parts = pager.split_whitespace();
+        let program = parts.next()?;
+        let mut args: Vec<&str> = parts.collect();
+        if args.is_empty() && Path::new(program).file_name().is_some_and(|name| name == "less") {
+            // Without -R, less shows the escape codes instead of the colours
+            args.push("-R");
+        }
+
+        let mut command = Command::new(program);
+        command.args(args).stdin(Stdio::piped());
+        if let Some(group) = group {
+            command.process_group(group as i32);
+        }
+        let child = command.spawn().ok()?;
+        Some(Pager { child })
+    }
+
+    // Where the output is written. Writes fail once the pager has been quit, which
+    // doesn't stop the files being read
+    pub fn input(&mut self) -> Option<ChildStdin> {
+        self.child.stdin.take()
+    }
+
+    // Waits for the pager to be quit, after its input has been closed
+    pub fn wait(mut self) {
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
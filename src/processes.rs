@@ -1,14 +1,47 @@
-
-use std::io::{self, BufRead, BufReader, Read};
-use std::process::{Command, Stdio};
+use std::env;
+use std::fs;
+use std::mem;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc::{self, Sender, Receiver};
-use std::thread;
+use std::sync::mpsc::{self, SyncSender, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use memchr::memchr;
 
 use crate::input_reader::CommandCall;
+use crate::recording::Recorder;
+use crate::signals::{self, LentTerminal};
+
+// How long to wait before checking a followed log file for new output
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+// Like `tail -f`, following a log file starts with its last few lines, which are looked
+// for in the end of the file
+const FOLLOW_START_LINES: usize = 10;
+const FOLLOW_START_BYTES: u64 = 64 * 1024;
 
-#[derive(Copy, Clone, Debug)]
-pub enum LineSource { Out, Error }
+// How many batches of lines can wait to be printed before the commands writing them are held up
+const CHANNEL_CAPACITY: usize = 64;
+const MAX_BATCH_LINES: usize = 256;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum LineSource { #[default] Out, Error }
+
+impl LineSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineSource::Out => "stdout",
+            LineSource::Error => "stderr",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct LineMessage {
@@ -17,59 +50,375 @@ pub struct LineMessage {
     pub close_stream: bool,
 }
 
+// Lines are sent in batches, so a fast command doesn't need a message for every line
+pub type LineBatch = Vec<LineMessage>;
+
+// The channel the output of a command, or log file, is sent to
+pub struct Output {
+    pub lines: Receiver<LineBatch>,
+    child: Option<Child>,
+    // The terminal the command was given, which ds takes back once it's finished
+    terminal: Option<LentTerminal>,
+}
+
+impl Output {
+    // Waits for the command to finish and returns its exit code. Commands killed by 
+    // a signal don't have one, and neither do uncompressed log files
+    pub fn wait(&mut self) -> Option<i32> {
+        let status = self.child.as_mut()?.wait().ok();
+        if let Some(terminal) = self.terminal.take() {
+            terminal.restore();
+        }
+        status?.code()
+    }
+
+    // Records the lines on their way to being read, returning the thread to wait for
+    // before the recording is used
+    pub fn record(&mut self, recorder: Recorder) -> io::Result<JoinHandle<()>> {
+        let (_, closed) = mpsc::sync_channel(0);
+        let lines = mem::replace(&mut self.lines, closed);
+        let (lines, recording) = recorder.record(lines)?;
+        self.lines = lines;
+        Ok(recording)
+    }
+
+    // The command is started in its own process group, with the same id as the command
+    pub fn process_group(&self) -> Option<u32> {
+        self.child.as_ref().map(|child| child.id())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Compression { Plain, Gzip, Zstd }
+
 
 // This takes a stream (stdout or stderr) from a process, formats it's output into LineMessages 
 // and writes it's output to the sender
-fn capture_stream<R>(mut stream: R, stream_type: LineSource, tx: Sender<LineMessage>)
+fn capture_stream<R>(stream: R, stream_type: LineSource, tx: SyncSender<LineBatch>)
 where 
     R: Read + Send + 'static 
 { 
     // Spawn a tread to listen to the output of this stream and send it to the channel
     let _ = thread::Builder::new() 
         .name("Capturing output".into()) 
-        .spawn(move || loop {
-            let mut str_buffer = String::with_capacity(80);
-            let mut buffer = BufReader::new(&mut stream);
+        .spawn(move || {
+            let mut byte_buffer = Vec::with_capacity(80);
+            let mut batch = Vec::with_capacity(MAX_BATCH_LINES);
+            let mut buffer = BufReader::with_capacity(READ_BUFFER_SIZE, stream);
             // Repeatedly read lines from the stream and writing to the channel
             loop {
-                let read_result = buffer.read_line(&mut str_buffer);
-                match read_result {
-                    Ok(code) => {
-                        let should_close = code == 0;
-                        let message = LineMessage {
-                            line:  str_buffer.clone(),
-                            source: stream_type,
-                            close_stream: should_close,
-                        };
+                // Read raw bytes so that invalid UTF-8 in the output doesn't end the stream
+                let read_result = buffer.read_until(b'\n', &mut byte_buffer);
+                let should_close = !matches!(read_result, Ok(n) if n > 0);
+                // Valid UTF-8 is moved into the message rather than copied
+                let capacity = byte_buffer.len().max(80);
+                let line = match String::from_utf8(mem::replace(&mut byte_buffer, Vec::with_capacity(capacity))) {
+                    Ok(line) => line,
+                    Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                };
+                batch.push(LineMessage {
+                    line,
+                    source: stream_type,
+                    close_stream: should_close,
+                });
 
-                        let _ = tx.send(message);
+                // Lines are held back while there are more to read, but sent before a read which
+                // could wait for the command, e.g. after a prompt without a newline, so its output
+                // still appears straight away
+                let line_buffered = memchr(b'\n', buffer.buffer()).is_some();
+                if should_close || batch.len() >= MAX_BATCH_LINES || !line_buffered {
+                    let _ = tx.send(mem::replace(&mut batch, Vec::with_capacity(MAX_BATCH_LINES)));
+                }
 
-                        if should_close {  // End of stream 
-                            break;
-                        }
-                    },
-                    Err(_msg) => {
-                        todo!();
-                    }
+                if should_close {  // End of stream 
+                    break;
                 }
-                str_buffer.clear();
             }
         });
 }
 
-pub fn run_command(command: &CommandCall) -> io::Result<Receiver<LineMessage>> {
-    let output = Command::new(command.command.clone())
-        .args(command.args.clone())
+// Sends a close message for a stream that will never produce any output
+fn close_stream(stream_type: LineSource, tx: &SyncSender<LineBatch>) {
+    let _ = tx.send(vec!(LineMessage {
+        line: String::new(),
+        source: stream_type,
+        close_stream: true,
+    }));
+}
+
+pub fn run_command(command: &CommandCall) -> io::Result<Output> {
+    let mut cmd = Command::new(command.command.clone());
+    cmd.args(command.args.clone())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()?;
+        // A group of its own lets ds stop the command and anything it starts, together
+        .process_group(0);
 
-    let stdout = output.stdout.unwrap();
-    let stderr = output.stderr.unwrap();
+    // The command gets the terminal while it runs, so it can still read from it
+    let terminal = signals::foreground_terminal();
+    if let Some(terminal) = terminal {
+        unsafe { cmd.pre_exec(move || signals::take_terminal(terminal)) };
+    }
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            // The command can fail to start after taking the terminal
+            if let Some(terminal) = terminal {
+                signals::restore_terminal(terminal);
+            }
+            return Err(err);
+        }
+    };
+    let terminal = terminal.map(|terminal| LentTerminal::new(terminal, child.id()));
 
-    let (tx, rx): (Sender<LineMessage>, Receiver<LineMessage>) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let (tx, rx): (SyncSender<LineBatch>, Receiver<LineBatch>) = mpsc::sync_channel(CHANNEL_CAPACITY);
     capture_stream(stdout, LineSource::Out, tx.clone());
     capture_stream(stderr, LineSource::Error, tx);
 
-    Ok(rx)
+    Ok(Output { lines: rx, child: Some(child), terminal })
+}
+
+// Runs the command with `$SHELL -c` so that pipes, globs and shell functions work. 
+// An interactive shell is needed to pick up the user's aliases
+pub fn run_in_shell(command: &CommandCall, interactive: bool) -> io::Result<Output> {
+    let shell = env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".to_string());
+
+    let mut args = vec!();
+    if interactive {
+        args.push("-i".to_string());
+    }
+    args.push("-c".to_string());
+    args.push(command.shell_line());
+
+    run_command(&CommandCall { command: shell, args })
+}
+
+
+// ====================== Log Files =========================
+
+// Reads an existing log file as though it were the output of a command. 
+// Compressed files are passed through the system's gzip/zstd, and if follow is set 
+// the file will keep being read as it grows
+pub fn read_log_file(path: &str, follow: bool) -> io::Result<Output> {
+    let (tx, rx): (SyncSender<LineBatch>, Receiver<LineBatch>) = mpsc::sync_channel(CHANNEL_CAPACITY);
+
+    match detect_compression(Path::new(path))? {
+        Compression::Plain => {
+            let file = fs::File::open(path)?;
+            // Closed first, as the channel can fill up before anything reads from it
+            close_stream(LineSource::Error, &tx);
+            if follow {
+                follow_file(file, tx);
+            } else {
+                capture_stream(file, LineSource::Out, tx);
+            }
+        }
+
+        _ if follow => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "compressed log files can't be followed"));
+        }
+
+        compression => {
+            let program = if compression == Compression::Gzip { "gzip" } else { "zstd" };
+            let decompress = CommandCall {
+                command: program.to_string(),
+                args: vec!("-dc".to_string(), path.to_string()),
+            };
+            return run_command(&decompress);
+        }
+    }
+
+    Ok(Output { lines: rx, child: None, terminal: None })
+}
+
+// Checks the first few bytes of the file to see if it's compressed
+fn detect_compression(path: &Path) -> io::Result<Compression> {
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    fs::File::open(path)?
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+
+    let compression = if magic.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::Plain
+    };
+    Ok(compression)
+}
+
+// Like `tail -f`, this sends the last lines in the file and then waits for new lines to be 
+// written. The stream is only closed once ds is interrupted or times out
+fn follow_file(mut file: fs::File, tx: SyncSender<LineBatch>) {
+    let _ = thread::Builder::new()
+        .name("Following log file".into())
+        .spawn(move || {
+            let start = last_lines_start(&mut file, FOLLOW_START_LINES).unwrap_or(0);
+            let _ = file.seek(SeekFrom::Start(start));
+            let mut byte_buffer = Vec::with_capacity(80);
+            let mut reader = BufReader::new(file);
+            loop {
+                match reader.read_until(b'\n', &mut byte_buffer) {
+                    Ok(0) if signals::should_stop() => {
+                        close_stream(LineSource::Out, &tx);
+                        break;
+                    }
+
+                    // At the end of the file, so wait for more to be written
+                    Ok(0) => {
+                        // If the file was truncated (e.g. by log rotation) start again from the top
+                        let position = reader.stream_position();
+                        let length = reader.get_ref().metadata().map(|meta| meta.len());
+                        if let (Ok(position), Ok(length)) = (position, length) {
+                            if length < position {
+                                let _ = reader.seek(SeekFrom::Start(0));
+                                byte_buffer.clear();
+                            }
+                        }
+                        thread::sleep(FOLLOW_INTERVAL);
+                    }
+
+                    // Only send complete lines, a partial line will be finished by a later read
+                    Ok(_) if byte_buffer.ends_with(b"\n") => {
+                        let message = LineMessage {
+                            line: String::from_utf8_lossy(&byte_buffer).into_owned(),
+                            source: LineSource::Out,
+                            close_stream: false,
+                        };
+                        if tx.send(vec!(message)).is_err() {
+                            break;
+                        }
+                        byte_buffer.clear();
+                    }
+                    Ok(_) => (),
+
+                    Err(_) => {
+                        close_stream(LineSource::Out, &tx);
+                        break;
+                    }
+                }
+            }
+        });
+}
+
+// Finds where the last lines of the file start. Only the end of a large file is read, so
+// fewer lines are found if they're very long
+fn last_lines_start(file: &mut fs::File, lines: usize) -> io::Result<u64> {
+    let length = file.metadata()?.len();
+    let chunk_start = length.saturating_sub(FOLLOW_START_BYTES);
+    file.seek(SeekFrom::Start(chunk_start))?;
+    let mut chunk = Vec::new();
+    file.read_to_end(&mut chunk)?;
+
+    // A newline at the very end finishes the last line, rather than starting another
+    let search = chunk.strip_suffix(b"\n").unwrap_or(&chunk);
+    let newlines: Vec<usize> = search.iter().enumerate()
+        .filter(|(_, byte)| **byte == b'\n')
+        .map(|(idx, _)| idx)
+        .collect();
+    let line_start = match newlines.len().checked_sub(lines) {
+        Some(idx) => newlines[idx] + 1,
+        None if chunk_start == 0 => 0,
+        // The start of the chunk is most likely in the middle of a line
+        None => newlines.first().map_or(0, |idx| idx + 1),
+    };
+    Ok(chunk_start + line_start as u64)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("ds-test-{}-{}", std::process::id(), name));
+        fs::File::create(&path).unwrap().write_all(contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn collect_lines(output: Output) -> Vec<String> {
+        output.lines.iter()
+            .flatten()
+            .filter(|message| !message.close_stream)
+            .map(|message| message.line)
+            .collect()
+    }
+
+    #[test]
+    fn sends_lines_before_a_partial_line() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"done\nPassword: ").unwrap();
+        let (tx, rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        capture_stream(reader, LineSource::Out, tx);
+
+        // The writer is still open, so the partial line could be waiting forever
+        let batch = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(batch[0].line, "done\n");
+        drop(writer);
+        let rest: Vec<String> = rx.iter().flatten().map(|message| message.line).collect();
+        assert_eq!(rest[0], "Password: ");
+    }
+
+    #[test]
+    fn detects_compressed_files() {
+        let plain = write_temp_file("plain.log", b"error: src/main.rs\n");
+        let gzip = write_temp_file("gzip.log", &[0x1f, 0x8b, 0x08, 0x00]);
+        let zstd = write_temp_file("zstd.log", &[0x28, 0xb5, 0x2f, 0xfd, 0x00]);
+
+        assert_eq!(detect_compression(Path::new(&plain)).unwrap(), Compression::Plain);
+        assert_eq!(detect_compression(Path::new(&gzip)).unwrap(), Compression::Gzip);
+        assert_eq!(detect_compression(Path::new(&zstd)).unwrap(), Compression::Zstd);
+    }
+
+    #[test]
+    fn reads_plain_log_file() {
+        let path = write_temp_file("read.log", b"first line\nsecond line\n");
+        let output = read_log_file(&path, false).unwrap();
+
+        assert_eq!(collect_lines(output), vec!("first line\n", "second line\n"));
+    }
+
+    #[test]
+    fn reads_log_file_with_invalid_utf8() {
+        let path = write_temp_file("binary.log", b"bad \xff byte\n");
+        let output = read_log_file(&path, false).unwrap();
+
+        assert_eq!(collect_lines(output), vec!("bad \u{fffd} byte\n"));
+    }
+
+    #[test]
+    fn follows_growing_log_file() {
+        let path = write_temp_file("follow.log", b"first line\npartial");
+        let rx = read_log_file(&path, true).unwrap().lines;
+        let mut lines = rx.iter().flatten();
+
+        let first = lines.find(|message| !message.close_stream).unwrap();
+        assert_eq!(first.line, "first line\n");
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(b" line\n").unwrap();
+        let second = lines.find(|message| !message.close_stream).unwrap();
+        assert_eq!(second.line, "partial line\n");
+    }
+
+    #[test]
+    fn follows_from_last_lines() {
+        let contents: String = (1..=25).map(|line| format!("line {}\n", line)).collect();
+        let path = write_temp_file("follow-end.log", contents.as_bytes());
+        let rx = read_log_file(&path, true).unwrap().lines;
+
+        let first = rx.iter().flatten().find(|message| !message.close_stream).unwrap();
+        assert_eq!(first.line, "line 16\n");
+
+        let mut file = fs::File::open(&path).unwrap();
+        assert_eq!(last_lines_start(&mut file, 30).unwrap(), 0);
+    }
 }
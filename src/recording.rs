@@ -0,0 +1,165 @@
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::processes::{LineBatch, LineMessage, LineSource};
+
+// ====================== Constants =========================
+
+// Recordings start with this, so an old or unrelated file isn't read as one
+const MAGIC: &[u8] = b"DSREC\x01";
+
+const CHANNEL_CAPACITY: usize = 64;
+const MAX_BATCH_LINES: usize = 256;
+
+const FROM_STDERR: u8 = 1;
+const CLOSES_STREAM: u8 = 2;
+
+// ====================== Recording =========================
+
+// Writes every line of a command's output to a file, so it can be replayed with fresh file
+// detection later. Each line is stored as the milliseconds since the command started (u32),
+// a byte of flags for its source and whether it closes the stream, its length (u32) and then
+// the line itself, with the numbers little endian
+pub struct Recorder {
+    out: BufWriter<fs::File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        out.write_all(MAGIC)?;
+        Ok(Recorder { out, start: Instant::now() })
+    }
+
+    // Passes the lines on to a new channel, recording them on the way through. The recording
+    // is only complete once the returned thread has finished, after the lines have all been sent
+    pub fn record(mut self, lines: Receiver<LineBatch>) -> io::Result<(Receiver<LineBatch>, JoinHandle<()>)> {
+        let (tx, rx): (SyncSender<LineBatch>, Receiver<LineBatch>) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let recording = thread::Builder::new()
+            .name("Recording output".into())
+            .spawn(move || {
+                for batch in lines {
+                    // A recording that can't be written shouldn't stop the output being shown
+                    let _ = batch.iter().try_for_each(|message| self.write(message));
+                    if tx.send(batch).is_err() {
+                        break;
+                    }
+                }
+                let _ = self.out.flush();
+            })?;
+        Ok((rx, recording))
+    }
+
+    fn write(&mut self, message: &LineMessage) -> io::Result<()> {
+        let millis = self.start.elapsed().as_millis().min(u32::MAX as u128) as u32;
+        let mut flags = 0;
+        if message.source == LineSource::Error {
+            flags |= FROM_STDERR;
+        }
+        if message.close_stream {
+            flags |= CLOSES_STREAM;
+        }
+
+        self.out.write_all(&millis.to_le_bytes())?;
+        self.out.write_all(&[flags])?;
+        self.out.write_all(&(message.line.len() as u32).to_le_bytes())?;
+        self.out.write_all(message.line.as_bytes())
+    }
+}
+
+
+// ====================== Replaying =========================
+
+// Sends the recorded lines as though they came from the command, without waiting
+// between them. A recording cut short, e.g. by ds being killed, still ends both streams
+pub fn replay(path: &Path) -> io::Result<Receiver<LineBatch>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ds recording"));
+    }
+
+    let (tx, rx): (SyncSender<LineBatch>, Receiver<LineBatch>) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let _ = thread::Builder::new()
+        .name("Replaying output".into())
+        .spawn(move || {
+            let mut closed = vec!();
+            let mut batch = Vec::with_capacity(MAX_BATCH_LINES);
+            while let Some(message) = read_message(&mut reader) {
+                if message.close_stream {
+                    closed.push(message.source);
+                }
+                batch.push(message);
+                if batch.len() >= MAX_BATCH_LINES && tx.send(std::mem::take(&mut batch)).is_err() {
+                    return;
+                }
+            }
+
+            for source in [LineSource::Out, LineSource::Error] {
+                if !closed.contains(&source) {
+                    batch.push(LineMessage { line: String::new(), source, close_stream: true });
+                }
+            }
+            let _ = tx.send(batch);
+        });
+    Ok(rx)
+}
+
+fn read_message<R: Read>(reader: &mut R) -> Option<LineMessage> {
+    let mut header = [0; 9];
+    reader.read_exact(&mut header).ok()?;
+    let flags = header[4];
+    let len = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    let mut line = vec![0; len];
+    reader.read_exact(&mut line).ok()?;
+    Some(LineMessage {
+        line: String::from_utf8_lossy(&line).into_owned(),
+        source: if flags & FROM_STDERR != 0 { LineSource::Error } else { LineSource::Out },
+        close_stream: flags & CLOSES_STREAM != 0,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(line: &str, source: LineSource, close_stream: bool) -> LineMessage {
+        LineMessage { line: line.to_string(), source, close_stream }
+    }
+
+    #[test]
+    fn replays_recorded_lines() {
+        let path = std::env::temp_dir().join(format!("ds-test-{}-replay.rec", std::process::id()));
+        let (tx, rx) = mpsc::sync_channel(4);
+        tx.send(vec!(message("error: src/main.rs:3\n", LineSource::Error, false))).unwrap();
+        tx.send(vec!(message("done\n", LineSource::Out, false), message("", LineSource::Out, true))).unwrap();
+        drop(tx);
+
+        let (recorded, recording) = Recorder::create(&path).unwrap().record(rx).unwrap();
+        assert_eq!(recorded.iter().flatten().count(), 3);
+        recording.join().unwrap();
+
+        let replayed: Vec<(String, LineSource, bool)> = replay(&path).unwrap().iter()
+            .flatten()
+            .map(|message| (message.line, message.source, message.close_stream))
+            .collect();
+        assert_eq!(replayed, vec!(
+            ("error: src/main.rs:3\n".to_string(), LineSource::Error, false),
+            ("done\n".to_string(), LineSource::Out, false),
+            (String::new(), LineSource::Out, true),
+            // Stderr was never closed in the recording
+            (String::new(), LineSource::Error, true),
+        ));
+    }
+}
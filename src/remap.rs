@@ -0,0 +1,278 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::find_git_root;
+use crate::input_reader::CommandCall;
+
+// ====================== Constants =========================
+
+// Most long flags of `docker run`, `exec` and `compose run` take a value, which can be the
+// next argument and mustn't be taken for the image. These are the ones which don't
+const SWITCH_FLAGS: &[&str] = &[
+    "--rm", "--init", "--privileged", "--read-only", "--interactive", "--tty", "--detach",
+    "--no-healthcheck", "--oom-kill-disable", "--quiet", "--disable-content-trust", "--help",
+    "--publish-all", "--sig-proxy", "--no-deps", "--service-ports", "--use-aliases", "--build",
+    "--remove-orphans", "--quiet-pull", "--no-TTY", "--no-tty",
+];
+// Short flags are switches, and can be combined as in -it, other than these
+const SHORT_VALUE_FLAGS: &[&str] = &["-e", "-w", "-u", "-p", "-l", "-h", "-m", "-c", "-a"];
+
+// ====================== Types =========================
+
+// Replaces the start of a path printed by a command, for commands that run somewhere
+// the files have a different path e.g. in a container or on a build host
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remap {
+    from: String,
+    to: PathBuf,
+}
+
+impl Remap {
+    // Parses "FROM=TO", where TO can start with ~ or {root} for the root of the git repo
+    pub fn parse(value: &str) -> Result<Remap, String> {
+        let (from, to) = value.split_once('=')
+            .filter(|(from, _)| !from.trim_end_matches('/').is_empty())
+            .ok_or(format!("{}, is not a remap like FROM=TO", value))?;
+        Ok(Remap::new(from, &expand_to(to)))
+    }
+
+    fn new(from: &str, to: &Path) -> Remap {
+        let from = from.trim_end_matches('/');
+        Remap { from: from.to_string(), to: to.to_path_buf() }
+    }
+
+    // Finds the bind mounts in a `docker run` or `podman run` command, so the paths in
+    // the container are mapped back to the host. Only the flags before the image are
+    // docker's, the rest are passed to the command run in the container
+    pub fn detect(command: &CommandCall) -> Vec<Remap> {
+        let program = Path::new(&command.command).file_name().and_then(|name| name.to_str());
+        let subcommand = command.args.iter().position(|arg| arg == "run" || arg == "exec");
+        let subcommand = match (program, subcommand) {
+            (Some("docker" | "podman"), Some(idx)) => idx,
+            _ => return vec!(),
+        };
+
+        let mut remaps = vec!();
+        let mut args = command.args[subcommand + 1..].iter();
+        while let Some(arg) = args.next() {
+            let volume = match arg.as_str() {
+                "-v" | "--volume" => args.next().and_then(|value| parse_volume(value)),
+                "--mount" => args.next().and_then(|value| parse_mount(value)),
+                flag if takes_value(flag) => {
+                    args.next();
+                    None
+                }
+                // The image, or the container or service for exec
+                other if !other.starts_with('-') => break,
+                _ => {
+                    if let Some(value) = arg.strip_prefix("--volume=").or(arg.strip_prefix("-v")) {
+                        parse_volume(value)
+                    } else if let Some(value) = arg.strip_prefix("--mount=") {
+                        parse_mount(value)
+                    } else {
+                        None
+                    }
+                }
+            };
+            remaps.extend(volume);
+        }
+
+        if command.args[..subcommand].iter().any(|arg| arg == "compose") {
+            remaps.extend(compose_volumes(&command.args));
+        }
+        remaps
+    }
+
+    // Returns the path with the prefix replaced, if it starts with this remap's prefix
+    fn apply(&self, name: &str) -> Option<String> {
+        let rest = name.strip_prefix(&self.from)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        Some(self.to.join(rest.trim_start_matches('/')).to_string_lossy().into_owned())
+    }
+}
+
+// Applies the remap with the longest prefix that matches the start of the path, so
+// mounts inside other mounts are used
+pub fn remap_path<'a>(remaps: &[Remap], name: &'a str) -> Cow<'a, str> {
+    let remapped = remaps.iter()
+        .filter_map(|remap| Some((remap.from.len(), remap.apply(name)?)))
+        .max_by_key(|(prefix_len, _)| *prefix_len);
+    match remapped {
+        Some((_, remapped)) => Cow::Owned(remapped),
+        None => Cow::Borrowed(name),
+    }
+}
+
+
+// ====================== Helpers =========================
+
+fn expand_to(to: &str) -> PathBuf {
+    if let Some(rest) = to.strip_prefix("{root}") {
+        let root = env::current_dir().ok()
+            .map(|dir| find_git_root(&dir).unwrap_or(dir))
+            .unwrap_or_default();
+        return root.join(rest.trim_start_matches('/'));
+    }
+    match (to.strip_prefix('~'), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest.trim_start_matches('/')),
+        _ if to.is_empty() => PathBuf::from("."),
+        _ => PathBuf::from(to),
+    }
+}
+
+fn takes_value(flag: &str) -> bool {
+    if flag.starts_with("--") {
+        !flag.contains('=') && !SWITCH_FLAGS.contains(&flag)
+    } else {
+        SHORT_VALUE_FLAGS.contains(&flag)
+    }
+}
+
+// "-v ./src:/app/src:ro", named volumes like "cache:/root/.cache" aren't files on the host
+fn parse_volume(value: &str) -> Option<Remap> {
+    let mut parts = value.split(':');
+    let host = parts.next()?;
+    let container = parts.next()?;
+    let is_bind_mount = host.starts_with(['/', '.', '~']);
+    is_bind_mount.then(|| Remap::new(container, &expand_to(host)))
+}
+
+// Compose files list their volumes as "- ./src:/app/src" under a `volumes:` key, which are
+// picked out of the file line by line rather than parsing all of the YAML. Relative paths
+// are from the directory of the compose file
+fn compose_volumes(args: &[String]) -> Vec<Remap> {
+    let file_arg = args.iter()
+        .position(|arg| arg == "-f" || arg == "--file")
+        .and_then(|idx| args.get(idx + 1));
+    let names = ["compose.yaml", "compose.yml", "docker-compose.yaml", "docker-compose.yml"];
+    let (contents, dir) = match file_arg {
+        Some(file) => (fs::read_to_string(file).ok(), Path::new(file).parent().unwrap_or(Path::new(""))),
+        None => (names.iter().find_map(|name| fs::read_to_string(name).ok()), Path::new("")),
+    };
+
+    volume_items(&contents.unwrap_or_default())
+        .filter_map(|volume| parse_volume(volume.trim_matches(['"', '\''])))
+        .map(|remap| if remap.to.is_relative() && !dir.as_os_str().is_empty() {
+            Remap { to: dir.join(remap.to.strip_prefix(".").unwrap_or(&remap.to)), ..remap }
+        } else {
+            remap
+        })
+        .collect()
+}
+
+// The list items indented under each `volumes:` key
+fn volume_items(yaml: &str) -> impl Iterator<Item = &str> {
+    // The indent of the `volumes:` key the lines are under, if they are
+    let mut volumes_indent = None;
+    yaml.lines().filter_map(move |line| {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+        let indent = line.len() - line.trim_start().len();
+        if volumes_indent.is_some_and(|volumes_indent| indent <= volumes_indent) {
+            volumes_indent = None;
+        }
+
+        if trimmed == "volumes:" {
+            volumes_indent = Some(indent);
+            None
+        } else if volumes_indent.is_some() {
+            trimmed.strip_prefix("- ")
+        } else {
+            None
+        }
+    })
+}
+
+// "--mount type=bind,source=./src,target=/app/src"
+fn parse_mount(value: &str) -> Option<Remap> {
+    let mut is_bind = false;
+    let mut source = None;
+    let mut target = None;
+    for option in value.split(',') {
+        match option.split_once('=') {
+            Some(("type", kind)) => is_bind = kind == "bind",
+            Some(("source" | "src", path)) => source = Some(path),
+            Some(("target" | "destination" | "dst", path)) => target = Some(path),
+            _ => (),
+        }
+    }
+    match (is_bind, source, target) {
+        (true, Some(source), Some(target)) => Some(Remap::new(target, &expand_to(source))),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> CommandCall {
+        CommandCall {
+            command: "docker".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn remaps_prefixes() {
+        let remaps = vec!(Remap::parse("/app=.").unwrap(), Remap::parse("/build/=/home/me/src").unwrap());
+        assert_eq!(remap_path(&remaps, "/app/src/x.py"), "./src/x.py");
+        assert_eq!(remap_path(&remaps, "/build/lib.c"), "/home/me/src/lib.c");
+        assert_eq!(remap_path(&remaps, "/application/x.py"), "/application/x.py");
+    }
+
+    #[test]
+    fn rejects_remaps_without_a_prefix() {
+        assert!(Remap::parse("/app").is_err());
+        assert!(Remap::parse("=/app").is_err());
+        assert!(Remap::parse("/=/app").is_err());
+    }
+
+    #[test]
+    fn detects_docker_bind_mounts() {
+        let remaps = Remap::detect(&command(&["run", "-v", "./src:/app/src:ro", "-v", "cache:/cache",
+                                              "--mount", "type=bind,source=/data,target=/mnt", "image"]));
+        assert_eq!(remaps, vec!(Remap::new("/app/src", Path::new("./src")), Remap::new("/mnt", Path::new("/data"))));
+
+        assert!(Remap::detect(&command(&["ps", "-v", "./src:/app"])).is_empty());
+    }
+
+    #[test]
+    fn stops_detecting_at_the_image() {
+        let remaps = Remap::detect(&command(&["run", "-e", "MODE=test", "-v", "./src:/app", "image",
+                                              "tool", "-v", "./other:/other"]));
+        assert_eq!(remaps, vec!(Remap::new("/app", Path::new("./src"))));
+    }
+
+    #[test]
+    fn skips_values_of_docker_flags() {
+        let remaps = Remap::detect(&command(&["run", "--cpus", "2", "--add-host", "db:10.0.0.2", "-it", "--rm",
+                                              "--pull", "always", "-v", "./src:/app", "image"]));
+        assert_eq!(remaps, vec!(Remap::new("/app", Path::new("./src"))));
+    }
+
+    #[test]
+    fn reads_compose_paths_from_its_directory() {
+        let dir = env::temp_dir().join(format!("ds-test-{}-compose", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("compose.yaml");
+        fs::write(&file, "services:\n  app:\n    volumes:\n      - ./src:/app/src\n").unwrap();
+
+        let args: Vec<String> = ["compose", "-f", &file.to_string_lossy(), "run", "app"].iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(compose_volumes(&args), vec!(Remap::new("/app/src", &dir.join("src"))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reads_only_compose_volumes() {
+        let yaml = "services:\n  app:\n    ports:\n      - ./8080:80\n    volumes:\n      - ./src:/app/src\n      # cache\n      - \"./data:/data\"\n    command:\n      - ./run:/x\n";
+        assert_eq!(volume_items(yaml).collect::<Vec<_>>(), vec!("./src:/app/src", "\"./data:/data\""));
+    }
+}
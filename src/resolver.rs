@@ -0,0 +1,256 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::Receiver;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::git::GitStatus;
+use crate::input_reader::Config;
+use crate::line_reader::{find_file, File};
+
+// ====================== Constants =========================
+
+// How long a candidate that isn't a file is remembered for when following a log file with
+// --tail, as a file can be created after it was first mentioned
+const MISS_LIFETIME: Duration = Duration::from_secs(1);
+
+// Most words with a dot in them aren't files, e.g. timestamps and versions, so only the
+// most recent are remembered to keep a long log from using ever more memory
+const MAX_MISSES: usize = 100_000;
+
+// ====================== Types =========================
+
+#[derive(Debug)]
+enum Lookup {
+    // Claimed by the worker, but not checked yet
+    Pending,
+    Found(File),
+    // The candidate wasn't a file when it was checked
+    Missing(Instant),
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    lookups: HashMap<String, Lookup>,
+    // The candidates that weren't files, oldest first
+    misses: VecDeque<String>,
+    // Set once the worker has finished, or panicked, so nothing more is left pending
+    worker_stopped: bool,
+}
+
+// Finds the file each candidate refers to, remembering both the files found and the
+// candidates that aren't files, as test suites can print the same paths thousands of times.
+// Candidates can be claimed in batches and checked on a worker thread, while earlier
+// lines are being printed
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    config: &'a Config,
+    git: GitStatus,
+    cache: Mutex<Cache>,
+    resolved: Condvar,
+    miss_lifetime: Option<Duration>,
+    max_misses: usize,
+}
+
+// Stops the worker on drop, including when it panics, so `find` never waits for a lookup
+// which won't happen
+struct StopWorker<'r, 'a>(&'r Resolver<'a>);
+
+impl<'a> Resolver<'a> {
+    pub fn new(config: &'a Config, git: GitStatus) -> Resolver<'a> {
+        Resolver {
+            config,
+            git,
+            cache: Mutex::new(Cache::default()),
+            resolved: Condvar::new(),
+            miss_lifetime: config.tail.then_some(MISS_LIFETIME),
+            max_misses: MAX_MISSES,
+        }
+    }
+
+    fn is_current(&self, lookup: &Lookup) -> bool {
+        match (lookup, self.miss_lifetime) {
+            (Lookup::Missing(checked), Some(lifetime)) => checked.elapsed() < lifetime,
+            _ => true,
+        }
+    }
+
+    // Remembers a lookup, forgetting the oldest miss once there are too many
+    fn insert(&self, cache: &mut Cache, candidate: String, lookup: Lookup) {
+        if matches!(lookup, Lookup::Missing(_)) {
+            cache.misses.push_back(candidate.clone());
+            if cache.misses.len() > self.max_misses {
+                let oldest = cache.misses.pop_front().unwrap_or_default();
+                if matches!(cache.lookups.get(&oldest), Some(Lookup::Missing(_))) {
+                    cache.lookups.remove(&oldest);
+                }
+            }
+        }
+        cache.lookups.insert(candidate, lookup);
+    }
+
+    // Marks candidates as being looked up, returning the ones which weren't already
+    pub fn claim<'c>(&self, candidates: impl IntoIterator<Item = &'c str>) -> Vec<String> {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.worker_stopped {
+            return vec!();
+        }
+        let mut claimed = vec!();
+        for candidate in candidates {
+            if !cache.lookups.get(candidate).is_some_and(|lookup| self.is_current(lookup)) {
+                cache.lookups.insert(candidate.to_string(), Lookup::Pending);
+                claimed.push(candidate.to_string());
+            }
+        }
+        claimed
+    }
+
+    // Looks up the batches of claimed candidates, run on the worker thread
+    pub fn work(&self, batches: Receiver<Vec<String>>) {
+        let _stop = StopWorker(self);
+        for batch in batches {
+            self.resolve_batch(batch);
+        }
+    }
+
+    fn resolve_batch(&self, candidates: Vec<String>) {
+        for candidate in candidates {
+            let lookup = self.lookup(&candidate);
+            self.insert(&mut self.cache.lock().unwrap(), candidate, lookup);
+            self.resolved.notify_all();
+        }
+    }
+
+    // Finds the file for a candidate, waiting if the worker is already looking it up
+    pub fn find(&self, candidate: &str) -> Option<File> {
+        let mut cache = self.cache.lock().unwrap();
+        loop {
+            match cache.lookups.get(candidate) {
+                Some(Lookup::Found(file)) => return Some(file.clone()),
+                Some(lookup @ Lookup::Missing(_)) if self.is_current(lookup) => return None,
+                Some(Lookup::Pending) => cache = self.resolved.wait(cache).unwrap(),
+                _ => break,
+            }
+        }
+        drop(cache);
+
+        let lookup = self.lookup(candidate);
+        let file = match &lookup {
+            Lookup::Found(file) => Some(file.clone()),
+            _ => None,
+        };
+        self.insert(&mut self.cache.lock().unwrap(), candidate.to_string(), lookup);
+        file
+    }
+
+    fn lookup(&self, candidate: &str) -> Lookup {
+        match find_file(self.config, candidate) {
+            Some(mut file) => {
+                file.changed = self.git.is_changed(&file.name);
+                Lookup::Found(file)
+            }
+            None => Lookup::Missing(Instant::now()),
+        }
+    }
+}
+
+impl Drop for StopWorker<'_, '_> {
+    fn drop(&mut self) {
+        // The lock is poisoned if the worker panicked while holding it
+        let mut cache = self.0.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.worker_stopped = true;
+        cache.lookups.retain(|_, lookup| !matches!(lookup, Lookup::Pending));
+        self.0.resolved.notify_all();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn caches_files_and_non_files() {
+        let config = Config::default();
+        let resolver = Resolver::new(&config, GitStatus::default());
+
+        assert_eq!(resolver.find("Cargo.toml:3").map(|file| file.location()), Some("Cargo.toml:3".to_string()));
+        assert!(resolver.find("not_a_file.rs").is_none());
+        assert_eq!(resolver.cache.lock().unwrap().lookups.len(), 2);
+    }
+
+    #[test]
+    fn finds_claimed_candidates_from_worker() {
+        let config = Config::default();
+        let resolver = Resolver::new(&config, GitStatus::default());
+
+        let claimed = resolver.claim(["Cargo.toml", "Cargo.toml", "src/main.rs"]);
+        assert_eq!(claimed, vec!("Cargo.toml", "src/main.rs"));
+        assert!(resolver.claim(["Cargo.toml"]).is_empty());
+
+        let (batches, batch_rx) = mpsc::channel();
+        batches.send(claimed).unwrap();
+        drop(batches);
+        std::thread::scope(|scope| {
+            scope.spawn(|| resolver.work(batch_rx));
+            assert!(resolver.find("src/main.rs").is_some());
+        });
+    }
+
+    #[test]
+    fn finds_files_created_after_a_miss_when_following() {
+        let path = std::env::temp_dir().join(format!("ds-test-{}-created.rs", std::process::id()));
+        let name = path.to_string_lossy().into_owned();
+        let following = Config { tail: true, ..Config::default() };
+        let config = Config::default();
+        let follower = Resolver::new(&following, GitStatus::default());
+        let resolver = Resolver::new(&config, GitStatus::default());
+
+        assert!(follower.find(&name).is_none());
+        assert!(resolver.find(&name).is_none());
+        std::fs::write(&path, "").unwrap();
+        // Ages the misses, rather than waiting for them to expire
+        for resolver in [&follower, &resolver] {
+            resolver.cache.lock().unwrap().lookups
+                .insert(name.clone(), Lookup::Missing(Instant::now() - MISS_LIFETIME));
+        }
+        assert!(follower.find(&name).is_some());
+        // Without --tail, misses are remembered for the whole run
+        assert!(resolver.find(&name).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn forgets_the_oldest_misses() {
+        let config = Config::default();
+        let mut resolver = Resolver::new(&config, GitStatus::default());
+        resolver.max_misses = 2;
+
+        for name in ["a.missing", "b.missing", "c.missing"] {
+            assert!(resolver.find(name).is_none());
+        }
+        assert!(resolver.find("Cargo.toml").is_some());
+        let cache = resolver.cache.lock().unwrap();
+        assert!(!cache.lookups.contains_key("a.missing"));
+        assert!(cache.lookups.contains_key("c.missing"));
+        assert_eq!(cache.lookups.len(), 3);
+    }
+
+    #[test]
+    fn stopped_worker_leaves_nothing_pending() {
+        let config = Config::default();
+        let resolver = Resolver::new(&config, GitStatus::default());
+
+        assert_eq!(resolver.claim(["Cargo.toml"]), vec!("Cargo.toml"));
+        // The worker stops without looking up what was claimed, as it would if it panicked
+        let _ = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _stop = StopWorker(&resolver);
+                panic!("worker failed");
+            }).join()
+        });
+
+        assert!(resolver.find("Cargo.toml").is_some());
+        assert!(resolver.claim(["src/main.rs"]).is_empty());
+    }
+}
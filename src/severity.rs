@@ -0,0 +1,309 @@
+use std::path::Path;
+
+use memchr::memchr2_iter;
+
+use crate::input_reader::CommandCall;
+use crate::processes::LineSource;
+
+// ====================== Types =========================
+
+// How serious the line a file was found on is, ordered from most to least serious
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    #[default]
+    Mention,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Mention => "mention",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Severity> {
+        match value {
+            "error" => Some(Severity::Error),
+            "warning" => Some(Severity::Warning),
+            "note" => Some(Severity::Note),
+            "mention" => Some(Severity::Mention),
+            _ => None,
+        }
+    }
+}
+
+// Tools with output that needs more than a single line to classify
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Cargo,
+    Pytest,
+    Go,
+    Jest,
+    Make,
+}
+
+impl Rule {
+    pub fn parse(value: &str) -> Result<Rule, String> {
+        match value {
+            "cargo" => Ok(Rule::Cargo),
+            "pytest" => Ok(Rule::Pytest),
+            "go" => Ok(Rule::Go),
+            "jest" => Ok(Rule::Jest),
+            "make" => Ok(Rule::Make),
+            _ => Err(format!("Unknown rule '{}', expected cargo, pytest, go, jest or make", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::Cargo => "cargo",
+            Rule::Pytest => "pytest",
+            Rule::Go => "go",
+            Rule::Jest => "jest",
+            Rule::Make => "make",
+        }
+    }
+
+    // Picks the rule for a command from the program being run, e.g. `python -m pytest` or `npx jest`
+    pub fn detect(command: &CommandCall) -> Option<Rule> {
+        let program = |name: &str| {
+            Path::new(name).file_name().and_then(|name| name.to_str()).unwrap_or(name).to_string()
+        };
+        let mut words = vec!(program(&command.command));
+        words.extend(command.args.iter().take(2).map(|arg| program(arg)));
+
+        words.iter().find_map(|word| match word.as_str() {
+            "cargo" => Some(Rule::Cargo),
+            "pytest" | "py.test" => Some(Rule::Pytest),
+            "go" => Some(Rule::Go),
+            "jest" => Some(Rule::Jest),
+            "make" | "gmake" => Some(Rule::Make),
+            _ => None,
+        })
+    }
+}
+
+
+// ====================== Classifier =========================
+
+// Classifies each line of output. Some tools print the location of a message on a
+// later line than the message itself, so the severity of the last message is kept
+// as context for the lines which follow it
+#[derive(Debug, Default)]
+pub struct Classifier {
+    rules: Vec<Rule>,
+    context: Option<Severity>,
+}
+
+impl Classifier {
+    pub fn new(rules: &[Rule]) -> Classifier {
+        Classifier { rules: rules.to_vec(), context: None }
+    }
+
+    pub fn classify(&mut self, line: &str, source: LineSource) -> Severity {
+        let line = line.trim_end();
+
+        if let Some(severity) = self.classify_with_rules(line) {
+            return severity;
+        }
+        if let Some(severity) = classify_text(line) {
+            return severity;
+        }
+
+        // Tools are more likely to print problems to stderr, so a bare mention of an
+        // error there is trusted more than one in stdout, e.g. "make: *** [all] Error 2"
+        if source == LineSource::Error {
+            if find_ignore_case(line, "error").next().is_some() {
+                return Severity::Error;
+            } else if find_ignore_case(line, "warning").next().is_some() {
+                return Severity::Warning;
+            }
+        }
+        Severity::Mention
+    }
+
+    // Keeps the context up to date from a line which doesn't need classifying, as it has
+    // no files in it
+    pub fn observe(&mut self, line: &str) {
+        self.classify_with_rules(line.trim_end());
+    }
+
+    fn classify_with_rules(&mut self, line: &str) -> Option<Severity> {
+        let trimmed = line.trim_start();
+
+        for rule in &self.rules {
+            match rule {
+                // error[E0308]: mismatched types
+                //   --> src/main.rs:3:5
+                Rule::Cargo => {
+                    if trimmed.starts_with("--> ") || trimmed.starts_with("::: ") {
+                        return self.context;
+                    }
+                    if line.is_empty() {
+                        self.context = None;
+                    }
+                    else if !line.starts_with(' ') {
+                        // Message headers aren't indented, anything else ends the message
+                        self.context = classify_text(line);
+                    }
+                }
+
+                // tests/test_x.py:12: AssertionError
+                Rule::Pytest => {
+                    let is_location = trimmed.split_once(".py:")
+                        .is_some_and(|(_, rest)| rest.starts_with(|c: char| c.is_ascii_digit()));
+                    if is_location && !trimmed.starts_with("FAILED") {
+                        return Some(Severity::Error);
+                    }
+                }
+
+                // --- FAIL: TestThing (0.00s)
+                //     thing_test.go:12: expected 1, got 2
+                Rule::Go => {
+                    if trimmed.starts_with("--- FAIL") {
+                        self.context = Some(Severity::Error);
+                    }
+                    else if trimmed.starts_with("--- PASS") || trimmed.starts_with("--- SKIP") {
+                        self.context = None;
+                    }
+                    else if line.starts_with(' ') && trimmed.contains("_test.go:") {
+                        return self.context;
+                    }
+                }
+
+                // ● Suite › test name
+                //   at Object.<anonymous> (src/thing.test.js:12:5)
+                Rule::Jest => {
+                    if trimmed.starts_with('●') {
+                        self.context = Some(Severity::Error);
+                    }
+                    else if trimmed.starts_with("PASS ") || trimmed.starts_with("Tests:") {
+                        self.context = None;
+                    }
+                    else if trimmed.starts_with("at ") || trimmed.starts_with('>') {
+                        return self.context;
+                    }
+                }
+
+                // make: *** [Makefile:12: all] Error 2
+                Rule::Make => {
+                    if trimmed.starts_with("make") && trimmed.contains("***") {
+                        return Some(Severity::Error);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// Classifies a line by markers that most tools use
+fn classify_text(line: &str) -> Option<Severity> {
+    let trimmed = line.trim_start();
+    let bytes = line.as_bytes();
+
+    // A prefix is only a marker at the start of a message e.g. "error: " or "error[E0308]: "
+    let has_marker = |marker: &str| {
+        find_ignore_case(line, marker).any(|idx| {
+            let at_word_start = idx == 0 || !bytes[idx - 1].is_ascii_alphanumeric();
+            at_word_start && matches!(bytes.get(idx + marker.len()), Some(b':' | b'['))
+        })
+    };
+
+    if has_marker("error") || line.contains("FAILED") || line.contains("panicked at")
+        || trimmed.starts_with("E ") {
+        Some(Severity::Error)
+    } else if has_marker("warning") {
+        Some(Severity::Warning)
+    } else if has_marker("note") || has_marker("help") {
+        Some(Severity::Note)
+    } else {
+        None
+    }
+}
+
+// Finds where an ASCII word appears in a line, in any case, without lowercasing the line
+fn find_ignore_case<'a>(line: &'a str, word: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let (line, word) = (line.as_bytes(), word.as_bytes());
+    let first = word[0];
+    memchr2_iter(first.to_ascii_lowercase(), first.to_ascii_uppercase(), line)
+        .filter(move |&idx| line.get(idx..idx + word.len()).is_some_and(|found| found.eq_ignore_ascii_case(word)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(lines: &[&str], rules: &[Rule]) -> Vec<Severity> {
+        let mut classifier = Classifier::new(rules);
+        lines.iter().map(|line| classifier.classify(line, LineSource::Out)).collect()
+    }
+
+    #[test]
+    fn classifies_lines_by_text() {
+        let lines = [
+            "src/main.rs:3:1: error: expected ;",
+            "Warning: unused variable",
+            "thread 'main' panicked at src/main.rs:2:5:",
+            "E       assert 1 == 2",
+            "FAILED tests/test_x.py::test_y",
+            "note: defined here",
+            "Compiling src/errors.rs",
+        ];
+        let expected = [Severity::Error, Severity::Warning, Severity::Error, Severity::Error,
+                        Severity::Error, Severity::Note, Severity::Mention];
+        assert_eq!(classify(&lines, &[]), expected);
+    }
+
+    #[test]
+    fn trusts_stderr_more() {
+        let mut classifier = Classifier::new(&[]);
+        assert_eq!(classifier.classify("make: *** [all] Error 2", LineSource::Out), Severity::Mention);
+        assert_eq!(classifier.classify("make: *** [all] Error 2", LineSource::Error), Severity::Error);
+    }
+
+    #[test]
+    fn cargo_locations_follow_their_message() {
+        let lines = [
+            "warning: unused variable: `x`",
+            " --> src/main.rs:3:9",
+            "",
+            "error[E0308]: mismatched types",
+            "  --> src/main.rs:5:5",
+            "",
+            "  --> src/lib.rs:1:1",
+        ];
+        let severities = classify(&lines, &[Rule::Cargo]);
+        assert_eq!(severities[1], Severity::Warning);
+        assert_eq!(severities[4], Severity::Error);
+        assert_eq!(severities[6], Severity::Mention);
+    }
+
+    #[test]
+    fn go_and_pytest_locations_are_errors() {
+        let go = ["--- FAIL: TestThing (0.00s)", "    thing_test.go:12: expected 1"];
+        assert_eq!(classify(&go, &[Rule::Go])[1], Severity::Error);
+
+        let pytest = ["tests/test_x.py:12: AssertionError"];
+        assert_eq!(classify(&pytest, &[Rule::Pytest])[0], Severity::Error);
+    }
+
+    #[test]
+    fn detects_rules_from_command() {
+        let command = |command: &str, args: &[&str]| CommandCall {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        };
+        assert_eq!(Rule::detect(&command("cargo", &["test"])), Some(Rule::Cargo));
+        assert_eq!(Rule::detect(&command("/usr/bin/python3", &["-m", "pytest"])), Some(Rule::Pytest));
+        assert_eq!(Rule::detect(&command("npx", &["jest"])), Some(Rule::Jest));
+        assert_eq!(Rule::detect(&command("ls", &["-l"])), None);
+    }
+}
@@ -0,0 +1,257 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use libc::c_int;
+
+// ====================== Constants =========================
+
+// How long a command gets to exit after being asked to stop, before it's killed
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// How often to check if the command has been stopped with Ctrl-Z, while it has the terminal
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+const FORWARDED_SIGNALS: [c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+// The process group of the running command, or 0 if there isn't one
+static PROCESS_GROUP: AtomicI32 = AtomicI32::new(0);
+// The last signal ds was sent, or 0 if it hasn't been sent one
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+static RECEIVED_COUNT: AtomicUsize = AtomicUsize::new(0);
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+static CONTINUED: AtomicBool = AtomicBool::new(false);
+
+
+// ====================== Forwarding =========================
+
+// Passes on the signal to the command, so it gets the chance to clean up and ds can show the
+// files from its output so far. Pressing Ctrl-C a second time kills the command outright.
+// Only atomics and kill are used here, as a signal handler can't safely do anything else
+extern "C" fn forward_signal(signal: c_int) {
+    RECEIVED.store(signal, Ordering::SeqCst);
+    let count = RECEIVED_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+    let group = PROCESS_GROUP.load(Ordering::SeqCst);
+    if group > 0 {
+        let signal = if count > 1 { libc::SIGKILL } else { signal };
+        unsafe { libc::kill(-group, signal) };
+    }
+}
+
+// Catches SIGINT, SIGTERM and SIGHUP while the command runs, passing them on to everything it
+// started. When the command has the terminal a Ctrl-C goes straight to its process group instead,
+// so only signals sent to ds itself are passed on. With no group, e.g. when following a log file,
+// the signal is only recorded. Anything recorded from an earlier run in watch mode is cleared
+pub fn forward_to(group: Option<u32>) {
+    RECEIVED.store(0, Ordering::SeqCst);
+    RECEIVED_COUNT.store(0, Ordering::SeqCst);
+    TIMED_OUT.store(false, Ordering::SeqCst);
+    PROCESS_GROUP.store(group.map_or(0, |group| group as i32), Ordering::SeqCst);
+    for signal in FORWARDED_SIGNALS {
+        set_handler(signal, forward_signal as extern "C" fn(c_int) as libc::sighandler_t);
+    }
+}
+
+// Puts back the default handlers, so Ctrl-C at the file prompt exits ds as normal
+pub fn stop_forwarding() {
+    PROCESS_GROUP.store(0, Ordering::SeqCst);
+    for signal in FORWARDED_SIGNALS {
+        set_handler(signal, libc::SIG_DFL);
+    }
+}
+
+fn set_handler(signal: c_int, handler: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+// The signal that interrupted the command, if there was one
+pub fn received() -> Option<c_int> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+// Checks if the command was stopped, either by a signal or by running out of time
+pub fn should_stop() -> bool {
+    received().is_some() || timed_out()
+}
+
+pub fn timed_out() -> bool {
+    TIMED_OUT.load(Ordering::SeqCst)
+}
+
+pub fn signal_name(signal: c_int) -> String {
+    match signal {
+        libc::SIGINT => "SIGINT".to_string(),
+        libc::SIGTERM => "SIGTERM".to_string(),
+        libc::SIGHUP => "SIGHUP".to_string(),
+        _ => format!("signal {}", signal),
+    }
+}
+
+
+// ====================== Terminal =========================
+
+// The terminal ds is in the foreground of, if it's run from one. Commands are in their own
+// process group, so they have to be given the terminal to read from it, or to start an
+// interactive shell, without being stopped
+pub fn foreground_terminal() -> Option<c_int> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO].into_iter().find(|fd| unsafe {
+        libc::isatty(*fd) == 1 && libc::tcgetpgrp(*fd) == libc::getpgrp()
+    })
+}
+
+// Run in the command's process just before it starts, so it's in the foreground from the
+// start. Only async-signal-safe calls can be made between fork and exec
+pub fn take_terminal(terminal: c_int) -> io::Result<()> {
+    unsafe {
+        // Changing the foreground group from the background sends SIGTTOU, which would stop it
+        libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(terminal, libc::getpid());
+        libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+    }
+    Ok(())
+}
+
+// Puts ds back in the foreground, ready for the prompt
+pub fn restore_terminal(terminal: c_int) {
+    unsafe { libc::tcsetpgrp(terminal, libc::getpgrp()) };
+    set_handler(libc::SIGTTOU, libc::SIG_DFL);
+}
+
+// The terminal while the command's process group has it. ds is in the background until it's
+// given back, so it has to pass on Ctrl-Z the way a shell would
+pub struct LentTerminal {
+    terminal: c_int,
+    finished: Arc<AtomicBool>,
+    watcher: JoinHandle<()>,
+}
+
+impl LentTerminal {
+    pub fn new(terminal: c_int, group: u32) -> LentTerminal {
+        // SIGTTOU would stop ds for writing the output if the terminal has `stty tostop` set
+        set_handler(libc::SIGTTOU, libc::SIG_IGN);
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let finished = finished.clone();
+            thread::spawn(move || {
+                while !finished.load(Ordering::SeqCst) {
+                    if is_stopped(group as i32) {
+                        suspend(terminal, group as i32);
+                    } else {
+                        thread::sleep(STOP_CHECK_INTERVAL);
+                    }
+                }
+            })
+        };
+        LentTerminal { terminal, finished, watcher }
+    }
+
+    // Called once the command, and the pager in its group, have finished
+    pub fn restore(self) {
+        self.finished.store(true, Ordering::SeqCst);
+        let _ = self.watcher.join();
+        restore_terminal(self.terminal);
+    }
+}
+
+// Checks if the command, or the pager, has been stopped since the last check. Only stops are
+// waited for, so the command is still left to be reaped when it exits
+fn is_stopped(group: i32) -> bool {
+    unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let result = libc::waitid(libc::P_PGID, group as libc::id_t, &mut info, libc::WSTOPPED | libc::WNOHANG);
+        result == 0 && info.si_pid() != 0
+    }
+}
+
+// Without a shell to continue it, the kernel drops the SIGTSTP and ds carries on, so the
+// command is continued straight away instead
+fn has_job_control() -> bool {
+    unsafe {
+        let parent = libc::getppid();
+        libc::getpgid(parent) != libc::getpgrp() && libc::getsid(parent) == libc::getsid(0)
+    }
+}
+
+extern "C" fn mark_continued(_signal: c_int) {
+    CONTINUED.store(true, Ordering::SeqCst);
+}
+
+// Stops ds along with the command, so the shell ds was started from gets the terminal back.
+// Once continued with `fg`, the command is given the terminal again and continued too
+fn suspend(terminal: c_int, group: i32) {
+    if has_job_control() {
+        CONTINUED.store(false, Ordering::SeqCst);
+        set_handler(libc::SIGCONT, mark_continued as extern "C" fn(c_int) as libc::sighandler_t);
+        unsafe {
+            libc::tcsetpgrp(terminal, libc::getpgrp());
+            libc::raise(libc::SIGTSTP);
+        }
+        // The stop can take effect after raise returns, so wait to be continued
+        while !CONTINUED.load(Ordering::SeqCst) {
+            thread::sleep(STOP_CHECK_INTERVAL);
+        }
+        set_handler(libc::SIGCONT, libc::SIG_DFL);
+    }
+
+    unsafe {
+        // Continuing with `bg` leaves the terminal with the shell
+        if libc::tcgetpgrp(terminal) == libc::getpgrp() {
+            libc::tcsetpgrp(terminal, group);
+        }
+        libc::kill(-group, libc::SIGCONT);
+    }
+}
+
+
+// ====================== Timeouts =========================
+
+// Stops the command once its time is up, asking with SIGTERM first and then
+// killing it if it's still running after the grace period
+pub struct Timeout {
+    finished: Sender<()>,
+    watcher: JoinHandle<()>,
+}
+
+impl Timeout {
+    pub fn start(group: Option<u32>, limit: Duration) -> Timeout {
+        let (finished, finished_rx) = mpsc::channel::<()>();
+        let watcher = thread::spawn(move || {
+            // The sender is dropped when the command finishes, which ends the wait early
+            if finished_rx.recv_timeout(limit) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            TIMED_OUT.store(true, Ordering::SeqCst);
+            signal_group(group, libc::SIGTERM);
+
+            if finished_rx.recv_timeout(GRACE_PERIOD) == Err(RecvTimeoutError::Timeout) {
+                signal_group(group, libc::SIGKILL);
+            }
+        });
+        Timeout { finished, watcher }
+    }
+
+    // Called once the command has exited, so it isn't stopped after the fact
+    pub fn finish(self) {
+        drop(self.finished);
+        let _ = self.watcher.join();
+    }
+}
+
+fn signal_group(group: Option<u32>, signal: c_int) {
+    if let Some(group) = group {
+        unsafe { libc::kill(-(group as i32), signal) };
+    }
+}
@@ -0,0 +1,265 @@
+use crate::input_reader::{Config, SortOrder};
+use crate::line_reader::File;
+use crate::theme::FileKind;
+use crate::file_list::FileList;
+
+// ====================== Constants =========================
+
+// Long messages are cut down so each file fits on one line of the summary
+const MAX_MESSAGE_WIDTH: usize = 80;
+
+// ====================== Files Only =========================
+
+// All the numbered locations found in a single file
+struct FileGroup<'a> {
+    name: &'a str,
+    hits: usize,
+    locations: Vec<&'a File>,
+}
+
+// Prints one line per file with the numbered locations found in it e.g.
+//   src/main.rs  3 hits  [1] 12:5  [4] 40
+pub fn print_files_only(config: &Config, files: &FileList) {
+    for line in files_only_lines(config, files) {
+        println!("{}", line);
+    }
+}
+
+fn files_only_lines(config: &Config, files: &FileList) -> Vec<String> {
+    let groups = group_files(config.sort, files);
+    let name_width = groups.iter().map(|group| group.name.len()).max().unwrap_or(0);
+
+    groups.iter().map(|group| {
+        let hits = if group.hits == 1 { "1 hit ".to_string() } else { format!("{} hits", group.hits) };
+        // The file is highlighted as its most serious location
+        let kind = group.locations.iter()
+            .min_by_key(|file| file.severity)
+            .map_or(FileKind::File, |file| FileKind::of(file));
+        let name = config.theme.paint(kind, &format!("{:name_width$}", group.name));
+        let mut line = format!("{name}  {hits:>7}");
+
+        for file in &group.locations {
+            line.push_str(&format!("  [{}]", file.idx));
+            match (file.line, file.column) {
+                (Some(row), Some(column)) => line.push_str(&format!(" {}:{}", row, column)),
+                (Some(row), None) => line.push_str(&format!(" {}", row)),
+                _ => (),
+            }
+        }
+        line
+    }).collect()
+}
+
+fn group_files(sort: SortOrder, files: &FileList) -> Vec<FileGroup<'_>> {
+    let mut groups: Vec<FileGroup> = vec!();
+    for file in files.iter() {
+        match groups.iter_mut().find(|group| group.name == file.name) {
+            Some(group) => {
+                group.hits += file.hits;
+                group.locations.push(file);
+            }
+            None => groups.push(FileGroup { name: &file.name, hits: file.hits, locations: vec!(file) }),
+        }
+    }
+
+    // Groups are already in order of first appearance
+    if sort == SortOrder::Path {
+        groups.sort_by_key(|group| group.name);
+    }
+    // Changed files are the likeliest to be picked, so they come first
+    groups.sort_by_key(|group| !group.locations.iter().any(|file| file.changed));
+    groups
+}
+
+
+// ====================== Changed Files =========================
+
+// Lists the files changed in git just before the prompt, as they're the likeliest to be
+// picked but can be far back in the output e.g.
+//   Changed: [2*. src/main.rs:12] [5*. src/lib.rs]
+pub fn print_changed_files(config: &Config, files: &FileList) {
+    if let Some(line) = changed_files_line(config, files) {
+        println!("{}", line);
+    }
+}
+
+fn changed_files_line(config: &Config, files: &FileList) -> Option<String> {
+    let changed: Vec<String> = files.iter()
+        .filter(|file| file.changed)
+        .map(|file| config.theme.paint(FileKind::of(file), &format!("[{}{}. {}]", file.idx, file.marker(), file.location())))
+        .collect();
+    (!changed.is_empty()).then(|| format!("Changed: {}", changed.join(" ")))
+}
+
+
+// ====================== Summary =========================
+
+// Prints a table of the numbered files, so they can be seen together before picking one
+pub fn print_summary(config: &Config, files: &FileList) {
+    for line in summary_lines(config, files) {
+        println!("{}", line);
+    }
+}
+
+fn summary_lines(config: &Config, files: &FileList) -> Vec<String> {
+    // The limit keeps the last files found, before changed files are moved to the top
+    let mut sorted: Vec<&File> = files.iter().collect();
+    if let Some(limit) = config.summary_limit {
+        let skipped = sorted.len().saturating_sub(limit);
+        sorted.drain(..skipped);
+    }
+    sorted.sort_by_key(|file| !file.changed);
+
+    let rows: Vec<(FileKind, [String; 5])> = sorted.iter().map(|file| {
+        let position = match (file.line, file.column) {
+            (Some(line), Some(column)) => format!("{}:{}", line, column),
+            (Some(line), None) => line.to_string(),
+            _ => String::new(),
+        };
+        let row = [
+            file.idx.to_string() + file.marker(), file.name.clone(), position, file.severity.name().to_string(),
+            truncate(file.message.trim(), MAX_MESSAGE_WIDTH),
+        ];
+        (FileKind::of(file), row)
+    }).collect();
+
+    let header = ["#", "File", "Line", "Severity", "Message"].map(String::from);
+    let widths: Vec<usize> = (0..4)
+        .map(|col| rows.iter().map(|(_, row)| row).chain([&header]).map(|row| row[col].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    let mut lines = vec!(format_row(&header, &widths, |name| name.to_string()));
+    for (kind, row) in &rows {
+        lines.push(format_row(row, &widths, |name| config.theme.paint(*kind, name)));
+    }
+    lines
+}
+
+fn format_row(row: &[String; 5], widths: &[usize], paint: impl Fn(&str) -> String) -> String {
+    let [idx, name, position, severity, message] = row;
+    let name = paint(&format!("{name:0$}", widths[1]));
+    format!("{idx:>0$}  {name}  {position:1$}  {severity:2$}  {message}", widths[0], widths[2], widths[3])
+        .trim_end()
+        .to_string()
+}
+
+fn truncate(text: &str, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_width - 3).collect();
+    truncated.push_str("...");
+    truncated
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::Theme;
+    use crate::severity::Severity;
+
+    fn underline_config() -> Config {
+        Config { theme: Theme::underline(), ..Config::default() }
+    }
+
+    fn file(name: &str, line: Option<usize>) -> File {
+        let mut file = File::new(name.to_string(), line, None);
+        file.message = format!("error in {}", name);
+        file.severity = Severity::Error;
+        file
+    }
+
+    fn test_files() -> FileList {
+        let mut files = FileList::new(Default::default());
+        files.add(file("src/main.rs", Some(12)));
+        files.add(file("Cargo.toml", None));
+        files.add(file("src/main.rs", Some(40)));
+        files.add(file("src/main.rs", Some(12)));
+        files
+    }
+
+    #[test]
+    fn groups_files_by_appearance() {
+        let files = test_files();
+        let groups = group_files(SortOrder::Appearance, &files);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "src/main.rs");
+        assert_eq!(groups[0].hits, 3);
+        assert_eq!(groups[0].locations.iter().map(|f| f.idx).collect::<Vec<_>>(), vec!(1, 3));
+        assert_eq!(groups[1].name, "Cargo.toml");
+    }
+
+    #[test]
+    fn groups_files_by_path() {
+        let files = test_files();
+        let groups = group_files(SortOrder::Path, &files);
+
+        assert_eq!(groups[0].name, "Cargo.toml");
+        assert_eq!(groups[1].name, "src/main.rs");
+    }
+
+    #[test]
+    fn formats_files_only_lines() {
+        let config = underline_config();
+        let lines = files_only_lines(&config, &test_files());
+
+        assert!(lines[0].ends_with("src/main.rs\x1B[0m   3 hits  [1] 12  [3] 40"));
+        assert!(lines[1].ends_with("Cargo.toml \x1B[0m   1 hit   [2]"));
+    }
+
+    #[test]
+    fn formats_summary_table() {
+        let config = underline_config();
+        let lines = summary_lines(&config, &test_files());
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "#  File         Line  Severity  Message");
+        assert_eq!(lines[1], "1  \x1B[4msrc/main.rs\x1B[0m  12    error     error in src/main.rs");
+        assert_eq!(lines[2], "2  \x1B[4mCargo.toml \x1B[0m        error     error in Cargo.toml");
+    }
+
+    #[test]
+    fn limits_summary_to_last_files() {
+        let config = Config { summary_limit: Some(1), ..underline_config() };
+        let lines = summary_lines(&config, &test_files());
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("3  "));
+    }
+
+    #[test]
+    fn changed_files_come_first() {
+        let mut files = FileList::new(Default::default());
+        files.add(file("src/main.rs", Some(12)));
+        let mut changed = file("Cargo.toml", None);
+        changed.changed = true;
+        files.add(changed);
+
+        let lines = summary_lines(&underline_config(), &files);
+        assert!(lines[1].starts_with("2*  "));
+        assert!(lines[2].starts_with(" 1  "));
+
+        let groups = group_files(SortOrder::Appearance, &files);
+        assert_eq!(groups[0].name, "Cargo.toml");
+    }
+
+    #[test]
+    fn lists_changed_files() {
+        let config = Config::default();
+        let mut files = test_files();
+        assert_eq!(changed_files_line(&config, &files), None);
+
+        let mut changed = file("src/lib.rs", Some(3));
+        changed.changed = true;
+        files.add(changed);
+        assert_eq!(changed_files_line(&config, &files).as_deref(), Some("Changed: [4*. src/lib.rs:3]"));
+    }
+
+    #[test]
+    fn truncates_long_messages() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("a long message", 10), "a long ...");
+    }
+}
@@ -0,0 +1,196 @@
+use std::env;
+use std::io::{stdout, IsTerminal};
+
+use crate::line_reader::File;
+use crate::severity::Severity;
+
+// ====================== Constants =========================
+
+const RED_TEXT: &str = "\x1B[91m";
+const GREEN_TEXT: &str = "\x1B[92m";
+const YELLOW_TEXT: &str = "\x1B[93m";
+const BLUE_TEXT: &str = "\x1B[94m";
+const MAGENTA_TEXT: &str = "\x1B[95m";
+const CYAN_TEXT: &str = "\x1B[96m";
+const UNDERLINE_TEXT: &str = "\x1B[4m";
+const RESET_TEXT: &str = "\x1B[0m";
+
+// ====================== Types =========================
+
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub enum ColourMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColourMode {
+    pub fn parse(value: &str) -> Result<ColourMode, String> {
+        match value {
+            "auto" => Ok(ColourMode::Auto),
+            "always" => Ok(ColourMode::Always),
+            "never" => Ok(ColourMode::Never),
+            _ => Err(format!("Invalid colour mode '{}', expected auto, always or never", value)),
+        }
+    }
+}
+
+// What is being highlighted, each of which can have its own style
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FileKind {
+    ErrorLocation,
+    WarningLocation,
+    NoteLocation,
+    File,
+    Directory,
+    Missing,
+}
+
+impl FileKind {
+    pub fn of(file: &File) -> FileKind {
+        if file.missing {
+            return FileKind::Missing;
+        }
+        match file.severity {
+            Severity::Error => FileKind::ErrorLocation,
+            Severity::Warning => FileKind::WarningLocation,
+            Severity::Note => FileKind::NoteLocation,
+            Severity::Mention if file.is_dir => FileKind::Directory,
+            Severity::Mention => FileKind::File,
+        }
+    }
+}
+
+// The escape codes used to highlight each kind of file. An empty style prints the text as is
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Theme {
+    pub error: String,
+    pub warning: String,
+    pub note: String,
+    pub file: String,
+    pub directory: String,
+    pub missing: String,
+}
+
+impl Theme {
+    fn colour() -> Theme {
+        Theme {
+            error: RED_TEXT.into(),
+            warning: YELLOW_TEXT.into(),
+            note: CYAN_TEXT.into(),
+            file: GREEN_TEXT.into(),
+            directory: BLUE_TEXT.into(),
+            missing: MAGENTA_TEXT.into(),
+        }
+    }
+
+    // Files are still marked out on terminals without colour
+    pub fn underline() -> Theme {
+        Theme {
+            error: UNDERLINE_TEXT.into(),
+            warning: UNDERLINE_TEXT.into(),
+            note: UNDERLINE_TEXT.into(),
+            file: UNDERLINE_TEXT.into(),
+            directory: UNDERLINE_TEXT.into(),
+            missing: UNDERLINE_TEXT.into(),
+        }
+    }
+
+    // Builds the theme for this run, from the flags, the environment and whether 
+    // stdout is a terminal
+    pub fn detect(mode: ColourMode, no_colour: bool) -> Theme {
+        let env_set = |name: &str| env::var(name).is_ok_and(|value| !value.is_empty());
+        let styled = match mode {
+            ColourMode::Always => true,
+            ColourMode::Never => false,
+            ColourMode::Auto => {
+                env::var("CLICOLOR_FORCE").is_ok_and(|value| !value.is_empty() && value != "0")
+                    || stdout().is_terminal()
+            }
+        };
+
+        if !styled {
+            Theme::default()
+        } else if no_colour || env_set("NO_COLOR") {
+            Theme::underline()
+        } else {
+            let mut theme = Theme::colour();
+            if let Ok(spec) = env::var("DS_COLORS") {
+                theme.apply_spec(&spec);
+            }
+            theme
+        }
+    }
+
+    // Reads styles in the format of DS_COLORS, e.g. "error=1;91:file=32". 
+    // Unknown kinds and invalid codes are ignored, as in LS_COLORS
+    fn apply_spec(&mut self, spec: &str) {
+        for entry in spec.split(':') {
+            let Some((kind, codes)) = entry.split_once('=') else { continue };
+            if codes.is_empty() || !codes.bytes().all(|b| b.is_ascii_digit() || b == b';') {
+                continue;
+            }
+
+            let style = format!("\x1B[{}m", codes);
+            match kind {
+                "error" => self.error = style,
+                "warning" => self.warning = style,
+                "note" => self.note = style,
+                "file" => self.file = style,
+                "dir" => self.directory = style,
+                "missing" => self.missing = style,
+                _ => (),
+            }
+        }
+    }
+
+    pub fn style(&self, kind: FileKind) -> &str {
+        match kind {
+            FileKind::ErrorLocation => &self.error,
+            FileKind::WarningLocation => &self.warning,
+            FileKind::NoteLocation => &self.note,
+            FileKind::File => &self.file,
+            FileKind::Directory => &self.directory,
+            FileKind::Missing => &self.missing,
+        }
+    }
+
+    pub fn paint(&self, kind: FileKind, text: &str) -> String {
+        let style = self.style(kind);
+        if style.is_empty() {
+            text.to_string()
+        } else {
+            format!("{style}{text}{RESET_TEXT}")
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_colour_spec() {
+        let mut theme = Theme::colour();
+        theme.apply_spec("error=1;31:dir=34:bogus=1:warning=red");
+
+        assert_eq!(theme.error, "\x1B[1;31m");
+        assert_eq!(theme.directory, "\x1B[34m");
+        assert_eq!(theme.warning, YELLOW_TEXT);
+        assert_eq!(theme.file, GREEN_TEXT);
+    }
+
+    #[test]
+    fn plain_theme_has_no_escape_codes() {
+        let theme = Theme::default();
+        assert_eq!(theme.paint(FileKind::File, "a.rs"), "a.rs");
+    }
+
+    #[test]
+    fn never_mode_has_no_escape_codes() {
+        assert_eq!(Theme::detect(ColourMode::Never, false), Theme::default());
+        assert_eq!(Theme::detect(ColourMode::Always, true), Theme::underline());
+    }
+}
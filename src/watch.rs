@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Duration;
+
+use libc::c_int;
+
+use crate::ignore::IgnoreRules;
+
+// ====================== Constants =========================
+
+// How long files have to stop changing before the command is rerun, so saving
+// several files at once only causes one run
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Editors either write files in place or write a new file and move it over the old one
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_DELETE
+    | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+
+const EVENT_SIZE: usize = mem::size_of::<libc::inotify_event>();
+const STDIN: c_int = 0;
+const INPUT_BUFFER_SIZE: usize = 1024;
+
+
+// ====================== Types =========================
+
+// What ended a wait in watch mode
+#[derive(Debug, PartialEq)]
+pub enum Wake {
+    Changed,
+    Input(String),
+}
+
+// Watches every directory under the root with inotify, other than ignored ones, while also
+// reading the file numbers entered at the prompt
+#[derive(Debug)]
+pub struct Watcher {
+    inotify: OwnedFd,
+    root: PathBuf,
+    dirs: HashMap<c_int, PathBuf>,
+    ignore: IgnoreRules,
+    stdin_open: bool,
+    // Read from stdin but not returned yet, as it's read straight from the file descriptor
+    // so that poll sees everything which hasn't been read
+    input: Vec<u8>,
+}
+
+impl Watcher {
+    pub fn new(root: &Path, ignore: IgnoreRules) -> io::Result<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut watcher = Watcher {
+            inotify: unsafe { OwnedFd::from_raw_fd(fd) },
+            root: root.to_path_buf(),
+            dirs: HashMap::new(),
+            ignore,
+            stdin_open: true,
+            input: vec!(),
+        };
+        watcher.watch_tree(root)?;
+        Ok(watcher)
+    }
+
+    // Waits for a file to change or for a line to be entered. Once stdin is closed,
+    // only changes are waited for
+    pub fn wait(&mut self) -> io::Result<Wake> {
+        loop {
+            if let Some(line) = self.next_line() {
+                return Ok(Wake::Input(line));
+            }
+
+            let mut fds = [poll_fd(self.inotify.as_raw_fd()), poll_fd(STDIN)];
+            let count = if self.stdin_open { 2 } else { 1 };
+            if unsafe { libc::poll(fds.as_mut_ptr(), count, -1) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+
+            if fds[0].revents & libc::POLLIN != 0 && self.read_events() {
+                self.settle();
+                return Ok(Wake::Changed);
+            }
+
+            if self.stdin_open && fds[1].revents & (libc::POLLIN | libc::POLLHUP) != 0 {
+                self.read_input()?;
+            }
+        }
+    }
+
+    // Reads the changes seen so far, like those made while the command ran, returning
+    // whether any were to files which aren't ignored
+    pub fn take_changes(&mut self) -> bool {
+        let changed = self.read_events();
+        if changed {
+            self.settle();
+        }
+        changed
+    }
+
+    fn read_input(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; INPUT_BUFFER_SIZE];
+        let len = unsafe { libc::read(STDIN, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if len < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(()) } else { Err(err) };
+        }
+
+        if len == 0 {
+            self.stdin_open = false;
+            // The last line doesn't need to end in a newline
+            if !self.input.is_empty() {
+                self.input.push(b'\n');
+            }
+        } else {
+            self.input.extend_from_slice(&buffer[..len as usize]);
+        }
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.input.iter().position(|byte| *byte == b'\n')?;
+        let line: Vec<u8> = self.input.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    // Waits until nothing has changed for the debounce time
+    fn settle(&mut self) {
+        loop {
+            let mut fds = [poll_fd(self.inotify.as_raw_fd())];
+            if unsafe { libc::poll(fds.as_mut_ptr(), 1, DEBOUNCE.as_millis() as c_int) } <= 0 {
+                break;
+            }
+            self.read_events();
+        }
+    }
+
+    // Watches a directory and every directory in it which isn't ignored
+    fn watch_tree(&mut self, dir: &Path) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let wd = unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.dirs.insert(wd, dir.to_path_buf());
+
+        for entry in fs::read_dir(dir)?.flatten() {
+            // Symlinks aren't followed, as they can point back up the tree
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            let path = entry.path();
+            if is_dir && !self.is_ignored(&path, true) {
+                // A directory that can't be watched, e.g. without permission, is skipped
+                let _ = self.watch_tree(&path);
+            }
+        }
+        Ok(())
+    }
+
+    // Reads all the events waiting, returning whether any were for files which aren't ignored
+    fn read_events(&mut self) -> bool {
+        let mut buffer = [0u8; 4096];
+        let mut changed = false;
+        loop {
+            let len = unsafe {
+                libc::read(self.inotify.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+            };
+            if len <= 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            while offset + EVENT_SIZE <= len as usize {
+                let event: libc::inotify_event = unsafe {
+                    ptr::read_unaligned(buffer.as_ptr().add(offset) as *const libc::inotify_event)
+                };
+                let name = &buffer[offset + EVENT_SIZE..offset + EVENT_SIZE + event.len as usize];
+                // Names are padded with nul bytes
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+                changed |= self.handle_event(event.wd, event.mask, name);
+                offset += EVENT_SIZE + event.len as usize;
+            }
+        }
+        changed
+    }
+
+    fn handle_event(&mut self, wd: c_int, mask: u32, name: &[u8]) -> bool {
+        // Events were lost, which could include new directories, so the tree is watched again
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            let root = self.root.clone();
+            let _ = self.watch_tree(&root);
+            return true;
+        }
+
+        // The watch was removed, because the directory was deleted
+        if mask & libc::IN_IGNORED != 0 {
+            self.dirs.remove(&wd);
+            return false;
+        }
+
+        let path = match self.dirs.get(&wd) {
+            Some(dir) => dir.join(OsStr::from_bytes(name)),
+            None => return false,
+        };
+        let is_dir = mask & libc::IN_ISDIR != 0;
+        if self.is_ignored(&path, is_dir) {
+            return false;
+        }
+
+        if is_dir && mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+            let _ = self.watch_tree(&path);
+        }
+        true
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name() == Some(OsStr::new(".git")) {
+            return true;
+        }
+        let name = path.to_string_lossy();
+        if is_dir {
+            self.ignore.is_ignored_dir(&name)
+        } else {
+            self.ignore.is_ignored(&name)
+        }
+    }
+}
+
+fn poll_fd(fd: c_int) -> libc::pollfd {
+    libc::pollfd { fd, events: libc::POLLIN, revents: 0 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sees_changes_in_new_directories() {
+        let root = std::env::temp_dir().join(format!("ds-test-{}-watch", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let mut watcher = Watcher::new(&root, IgnoreRules::default()).unwrap();
+        assert!(!watcher.read_events());
+
+        fs::create_dir(root.join("src")).unwrap();
+        assert!(watcher.read_events());
+
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        assert!(watcher.read_events());
+
+        fs::create_dir(root.join(".git")).unwrap();
+        assert!(!watcher.read_events());
+
+        // An overflowed queue has no watch or name
+        assert!(watcher.handle_event(-1, libc::IN_Q_OVERFLOW, b""));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn splits_input_into_lines() {
+        let root = std::env::temp_dir().join(format!("ds-test-{}-watch-input", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let mut watcher = Watcher::new(&root, IgnoreRules::default()).unwrap();
+
+        watcher.input.extend_from_slice(b"1\n2\n3");
+        assert_eq!(watcher.next_line().as_deref(), Some("1\n"));
+        assert_eq!(watcher.next_line().as_deref(), Some("2\n"));
+        assert_eq!(watcher.next_line(), None);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
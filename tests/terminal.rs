@@ -0,0 +1,92 @@
+// Runs ds from a pseudo-terminal, as it is from a shell, to check commands which use the
+// terminal aren't stopped while ds is reading their output
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ====================== Constants =========================
+
+const TIME_LIMIT: Duration = Duration::from_secs(10);
+
+// ====================== Helpers =========================
+
+// Starts ds with a new pseudo-terminal as its controlling terminal, returning the other end
+fn start_in_terminal(args: &[&str], name: &str) -> (Child, fs::File) {
+    let (mut master, mut slave) = (0, 0);
+    let result = unsafe {
+        libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), std::ptr::null())
+    };
+    assert_eq!(result, 0, "failed to open a pseudo-terminal");
+    let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+    let state_dir = env::temp_dir().join(format!("ds-test-{}-{}", std::process::id(), name));
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ds"));
+    command.args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        // The shell is started without the user's startup files
+        .env("SHELL", "/bin/sh")
+        .env("HOME", &state_dir)
+        .env_remove("ENV")
+        .env("XDG_STATE_HOME", &state_dir)
+        .stdin(Stdio::from(slave.try_clone().unwrap()))
+        .stdout(Stdio::from(slave.try_clone().unwrap()))
+        .stderr(Stdio::from(slave));
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0);
+            Ok(())
+        });
+    }
+    let child = command.spawn().unwrap();
+    (child, unsafe { fs::File::from_raw_fd(master) })
+}
+
+// Waits for ds to exit, returning everything it wrote to the terminal. A command stopped
+// by the terminal would leave ds waiting forever, so it's killed after the time limit
+fn finish(mut child: Child, terminal: fs::File) -> String {
+    let (tx, rx) = mpsc::channel();
+    let mut reader = terminal.try_clone().unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        // Reading fails once ds has exited and the terminal is closed
+        while let Ok(len @ 1..) = reader.read(&mut buffer) {
+            let _ = tx.send(buffer[..len].to_vec());
+        }
+    });
+
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > TIME_LIMIT {
+            let _ = child.kill();
+            let _ = child.wait();
+            panic!("ds didn't finish, output: {:?}", String::from_utf8_lossy(&rx.try_iter().flatten().collect::<Vec<_>>()));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    // Leaves time for the reader to catch up with the last of the output
+    thread::sleep(Duration::from_millis(100));
+    String::from_utf8_lossy(&rx.try_iter().flatten().collect::<Vec<_>>()).into_owned()
+}
+
+
+#[test]
+fn runs_interactive_shell_for_aliases() {
+    let (child, terminal) = start_in_terminal(&["--color", "never", "--paging", "never", "-s", "-a", "echo", "src/main.rs"], "aliases");
+    let output = finish(child, terminal);
+    assert!(output.contains(" src/main.rs]"), "unexpected output: {:?}", output);
+}
+
+#[test]
+fn command_reads_from_the_terminal() {
+    let (child, mut terminal) = start_in_terminal(&["--color", "never", "--paging", "never", "-s", "head", "-n1"], "head");
+    terminal.write_all(b"src/main.rs\n").unwrap();
+    let output = finish(child, terminal);
+    assert!(output.contains(" src/main.rs]"), "unexpected output: {:?}", output);
+}